@@ -0,0 +1,332 @@
+use core::fmt;
+use crate::{Key, KeyOption};
+
+/// Error returned when decoding invalid access bits.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum AccessBitsError {
+    /// Inverted copy of access bits doesn't match the non-inverted one.
+    InvertedMismatch,
+}
+
+impl fmt::Display for AccessBitsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AccessBitsError::InvertedMismatch => write!(f, "inverted copy of access bits doesn't match"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for AccessBitsError {}
+
+/// Access condition bits C1, C2, C3 of a single block (or group of blocks).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct AccessCondition(u8);
+
+impl AccessCondition {
+    /// Creates AccessCondition from individual bits.
+    pub fn new(c1: bool, c2: bool, c3: bool) -> Self {
+        AccessCondition((c1 as u8) << 2 | (c2 as u8) << 1 | c3 as u8)
+    }
+
+    /// Creates AccessCondition from bits in the form C1C2C3 (e.g. 0b001).
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        if bits < 8 {
+            Some(AccessCondition(bits))
+        } else {
+            None
+        }
+    }
+
+    /// Returns bits in the form C1C2C3.
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Returns bit C1.
+    pub fn c1(self) -> bool {
+        self.0 & 0b100 != 0
+    }
+
+    /// Returns bit C2.
+    pub fn c2(self) -> bool {
+        self.0 & 0b010 != 0
+    }
+
+    /// Returns bit C3.
+    pub fn c3(self) -> bool {
+        self.0 & 0b001 != 0
+    }
+
+    /// Returns true if this condition, when used for sector trailer, allows writing access
+    /// bits after authenticating with `key_option`.
+    pub fn access_bits_writable(self, key_option: KeyOption) -> bool {
+        match self.0 {
+            0b001 => key_option == KeyOption::KeyA,
+            0b011 | 0b101 => key_option == KeyOption::KeyB,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this condition, when used for data block, allows reading it after
+    /// authenticating with `key_option`.
+    pub fn data_readable(self, key_option: KeyOption) -> bool {
+        match self.0 {
+            0b011 | 0b101 => key_option == KeyOption::KeyB,
+            0b111 => false,
+            _ => true,
+        }
+    }
+
+    /// Returns true if this condition, when used for data block, allows writing it after
+    /// authenticating with `key_option`.
+    pub fn data_writable(self, key_option: KeyOption) -> bool {
+        match self.0 {
+            0b000 => true,
+            0b100 | 0b110 | 0b011 => key_option == KeyOption::KeyB,
+            _ => false,
+        }
+    }
+
+    /// Returns true if this condition, when used for sector trailer, allows reading key B.
+    ///
+    /// Key B can't be used for authentication in such case.
+    pub fn key_b_readable(self) -> bool {
+        // 000, 001 and 010
+        self.0 <= 0b010
+    }
+}
+
+/// Access bits stored in sector trailer (bytes 6 - 9).
+///
+/// Contains access conditions of three data block groups and of the trailer itself. In small
+/// sectors, each group is a single block, in large sectors of 4K tags, each group consists of
+/// five blocks. Byte 9 isn't used by the tag and can hold arbitrary user data.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct AccessBits {
+    data: [AccessCondition; 3],
+    trailer: AccessCondition,
+    user_byte: u8,
+}
+
+impl AccessBits {
+    /// Access bits of blank tags: data blocks and key B are readable and writable with key A,
+    /// key A and access bits are writable with key A.
+    pub const TRANSPORT: AccessBits = AccessBits {
+        data: [AccessCondition(0b000); 3],
+        trailer: AccessCondition(0b001),
+        user_byte: 0x69,
+    };
+
+    /// Creates AccessBits.
+    pub fn new(data: [AccessCondition; 3], trailer: AccessCondition, user_byte: u8) -> Self {
+        AccessBits { data: data, trailer: trailer, user_byte: user_byte }
+    }
+
+    /// Returns access condition of data block group (0 - 2).
+    ///
+    /// Panics if `group` is greater than 2.
+    pub fn data(&self, group: usize) -> AccessCondition {
+        self.data[group]
+    }
+
+    /// Returns access condition of the sector trailer.
+    pub fn trailer(&self) -> AccessCondition {
+        self.trailer
+    }
+
+    /// Returns user data byte.
+    pub fn user_byte(&self) -> u8 {
+        self.user_byte
+    }
+
+    /// Returns true if data block group (0 - 2) can be read after authenticating with
+    /// `key_option`.
+    ///
+    /// Key B can't be used at all if the trailer access condition allows reading it.
+    /// Panics if `group` is greater than 2.
+    pub fn data_readable(&self, group: usize, key_option: KeyOption) -> bool {
+        self.key_usable(key_option) && self.data[group].data_readable(key_option)
+    }
+
+    /// Returns true if data block group (0 - 2) can be written after authenticating with
+    /// `key_option`.
+    ///
+    /// Key B can't be used at all if the trailer access condition allows reading it.
+    /// Panics if `group` is greater than 2.
+    pub fn data_writable(&self, group: usize, key_option: KeyOption) -> bool {
+        self.key_usable(key_option) && self.data[group].data_writable(key_option)
+    }
+
+    fn key_usable(&self, key_option: KeyOption) -> bool {
+        key_option == KeyOption::KeyA || !self.trailer.key_b_readable()
+    }
+
+    // Returns C1, C2 and C3 of all four groups, each as nibble with group n at bit n.
+    fn nibbles(&self) -> (u8, u8, u8) {
+        let mut c1 = 0;
+        let mut c2 = 0;
+        let mut c3 = 0;
+        for (i, condition) in self.data.iter().chain(Some(&self.trailer)).enumerate() {
+            c1 |= (condition.c1() as u8) << i;
+            c2 |= (condition.c2() as u8) << i;
+            c3 |= (condition.c3() as u8) << i;
+        }
+        (c1, c2, c3)
+    }
+
+    /// Encodes access bits into bytes 6 - 9 of sector trailer.
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let (c1, c2, c3) = self.nibbles();
+        [
+            (!c2 & 0x0F) << 4 | (!c1 & 0x0F),
+            c1 << 4 | (!c3 & 0x0F),
+            c3 << 4 | c2,
+            self.user_byte,
+        ]
+    }
+
+    /// Decodes access bits from bytes 6 - 9 of sector trailer, checking inverted copies.
+    pub fn from_bytes(bytes: &[u8; 4]) -> Result<Self, AccessBitsError> {
+        let c1 = bytes[1] >> 4;
+        let c2 = bytes[2] & 0x0F;
+        let c3 = bytes[2] >> 4;
+        let c1_inv = bytes[0] & 0x0F;
+        let c2_inv = bytes[0] >> 4;
+        let c3_inv = bytes[1] & 0x0F;
+
+        if c1 != !c1_inv & 0x0F || c2 != !c2_inv & 0x0F || c3 != !c3_inv & 0x0F {
+            return Err(AccessBitsError::InvertedMismatch);
+        }
+
+        let condition = |i: u8| AccessCondition::new(c1 & (1 << i) != 0, c2 & (1 << i) != 0, c3 & (1 << i) != 0);
+        Ok(AccessBits::new([condition(0), condition(1), condition(2)], condition(3), bytes[3]))
+    }
+}
+
+/// Decoded sector trailer - keys and access bits.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SectorTrailer {
+    access_bits: AccessBits,
+    key_b: Option<Key>,
+}
+
+impl SectorTrailer {
+    /// Decodes sector trailer as read from the tag.
+    ///
+    /// Key A is never readable, tags return zeros instead, so its bytes are ignored - nonzero
+    /// bytes mean malformed response, not a key. Key B is `Some` only if access bits allow
+    /// reading it, otherwise the bytes are either zeros or don't represent a key.
+    pub fn from_bytes(bytes: &[u8; 16]) -> Result<Self, AccessBitsError> {
+        let mut access_bytes = [0; 4];
+        access_bytes.copy_from_slice(&bytes[6..10]);
+        let access_bits = AccessBits::from_bytes(&access_bytes)?;
+
+        let key_b = if access_bits.trailer().key_b_readable() {
+            let mut key_b = [0; 6];
+            key_b.copy_from_slice(&bytes[10..16]);
+            Some(Key::new(key_b))
+        } else {
+            None
+        };
+
+        Ok(SectorTrailer { access_bits: access_bits, key_b: key_b })
+    }
+
+    /// Returns key A, which is always `None`, because key A is never readable.
+    pub fn key_a(&self) -> Option<Key> {
+        None
+    }
+
+    /// Returns key B, if access bits allow reading it.
+    ///
+    /// Note that readable key B can't be used for authentication.
+    pub fn key_b(&self) -> Option<Key> {
+        self.key_b
+    }
+
+    /// Returns access bits.
+    pub fn access_bits(&self) -> AccessBits {
+        self.access_bits
+    }
+
+    /// Returns user data byte (byte 9).
+    pub fn user_byte(&self) -> u8 {
+        self.access_bits.user_byte()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_CONDITIONS: [u8; 8] = [0b000, 0b001, 0b010, 0b011, 0b100, 0b101, 0b110, 0b111];
+
+    fn condition(bits: u8) -> AccessCondition {
+        AccessCondition::from_bits(bits).unwrap()
+    }
+
+    #[test]
+    fn transport_configuration() {
+        assert_eq!(AccessBits::TRANSPORT.to_bytes(), [0xFF, 0x07, 0x80, 0x69]);
+        assert_eq!(AccessBits::from_bytes(&[0xFF, 0x07, 0x80, 0x69]), Ok(AccessBits::TRANSPORT));
+    }
+
+    #[test]
+    fn encoding_round_trip() {
+        for &trailer in &ALL_CONDITIONS {
+            for &data in &ALL_CONDITIONS {
+                let access_bits = AccessBits::new([condition(data), condition(!data & 0b111), condition(trailer)], condition(trailer), 0x42);
+                assert_eq!(AccessBits::from_bytes(&access_bits.to_bytes()), Ok(access_bits));
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_complement() {
+        // Each bit of the first three bytes is either a bit or its inverted copy
+        for byte in 0..3 {
+            for bit in 0..8 {
+                let mut bytes = AccessBits::TRANSPORT.to_bytes();
+                bytes[byte] ^= 1 << bit;
+                assert_eq!(AccessBits::from_bytes(&bytes), Err(AccessBitsError::InvertedMismatch));
+            }
+        }
+        // User byte isn't checked
+        assert!(AccessBits::from_bytes(&[0xFF, 0x07, 0x80, 0x00]).is_ok());
+    }
+
+    #[test]
+    fn access_bits_writable() {
+        use crate::KeyOption::{KeyA, KeyB};
+
+        // (condition, writable with key A, writable with key B) from the datasheet
+        let expected = [
+            (0b000, false, false),
+            (0b010, false, false),
+            (0b100, false, false),
+            (0b110, false, false),
+            (0b001, true, false),
+            (0b011, false, true),
+            (0b101, false, true),
+            (0b111, false, false),
+        ];
+        for &(bits, key_a, key_b) in &expected {
+            assert_eq!(condition(bits).access_bits_writable(KeyA), key_a, "condition {:03b}", bits);
+            assert_eq!(condition(bits).access_bits_writable(KeyB), key_b, "condition {:03b}", bits);
+        }
+    }
+
+    #[test]
+    fn key_a_is_never_read() {
+        let mut trailer = [0; 16];
+        trailer[0..6].copy_from_slice(&[0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5]);
+        trailer[6..10].copy_from_slice(&AccessBits::TRANSPORT.to_bytes());
+        trailer[10..16].copy_from_slice(&[0xB0, 0xB1, 0xB2, 0xB3, 0xB4, 0xB5]);
+        let decoded = SectorTrailer::from_bytes(&trailer).unwrap();
+        assert_eq!(decoded.key_a(), None);
+        // Transport configuration allows reading key B
+        assert_eq!(decoded.key_b(), Some(Key::new([0xB0, 0xB1, 0xB2, 0xB3, 0xB4, 0xB5])));
+        assert_eq!(decoded.user_byte(), 0x69);
+    }
+}
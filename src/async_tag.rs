@@ -0,0 +1,214 @@
+use core::future::Future;
+use core::marker::PhantomData;
+use crate::{command, TagError, Key, KeyOption, Error, AuthError, BlockOffset, DEFAULT_MAX_RESPONSE_LEN};
+use crate::numerics::{TagCapacity, SectorBlockOffset, AbsoluteBlockOffset, Cap1K, Cap4K};
+
+/// Represents NFC tag which could be Mifare tag, accessed using async reader driver.
+///
+/// This is async counterpart of `NFCTag`. Implementations can simply use
+/// `async fn transceive(...)`.
+pub trait AsyncNFCTag {
+    /// Error type of transceive() method.
+    type TransceiveError: TagError;
+
+    /// ID of tag. Must be 4 or 7 for valid Mifare tag.
+    fn tag_id(&self) -> &[u8];
+
+    /// This function will be used for communication with the tag.
+    fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> impl Future<Output = Result<usize, Self::TransceiveError>>;
+}
+
+/// Encapsulates Mifare tag accessed using async reader driver.
+///
+/// This is async counterpart of `MifareTag`. It supports only authentication, reading and
+/// writing of blocks; commands are framed the same way as in `MifareTag`.
+pub struct AsyncMifareTag<T, Cap = Cap4K> {
+    tag: T,
+    max_response_len: usize,
+    _phantom: PhantomData<Cap>,
+}
+
+impl<T: AsyncNFCTag> AsyncMifareTag<T> {
+    /// Checks whether tag_id has correct length and creates AsyncMifareTag with 4K capacity.
+    pub fn new(tag: T) -> Option<Self> {
+        AsyncMifareTag::with_capacity(tag)
+    }
+}
+
+impl<T: AsyncNFCTag> AsyncMifareTag<T, Cap1K> {
+    /// Checks whether tag_id has correct length and creates AsyncMifareTag with 1K capacity.
+    pub fn new_1k(tag: T) -> Option<Self> {
+        AsyncMifareTag::with_capacity(tag)
+    }
+}
+
+impl<T: AsyncNFCTag, Cap: TagCapacity> AsyncMifareTag<T, Cap> {
+    /// Checks whether tag_id has correct length and creates AsyncMifareTag with capacity `Cap`.
+    pub fn with_capacity(tag: T) -> Option<Self> {
+        let id_len = tag.tag_id().len();
+        if id_len == 4 || id_len == 7 {
+            Some(AsyncMifareTag { tag: tag, max_response_len: DEFAULT_MAX_RESPONSE_LEN, _phantom: PhantomData })
+        } else {
+            None
+        }
+    }
+
+    /// Sets maximum length of response accepted from the reader.
+    ///
+    /// See `MifareTag::set_max_response_len()`.
+    pub fn set_max_response_len(&mut self, max_response_len: usize) {
+        self.max_response_len = max_response_len;
+    }
+
+    /// Returns maximum length of response accepted from the reader.
+    pub fn max_response_len(&self) -> usize {
+        self.max_response_len
+    }
+
+    /// Returns id of underlying tag.
+    pub fn tag_id(&self) -> &[u8] {
+        self.tag.tag_id()
+    }
+
+    /// Sends arbitrary data to the tag and receives response.
+    ///
+    /// See `MifareTag::transceive_raw()`.
+    pub async fn transceive_raw(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Error<T::TransceiveError>> {
+        let max = ::core::cmp::min(data_from_tag.len(), self.max_response_len);
+        let len = self.tag.transceive(data_to_tag, &mut data_from_tag[..max]).await.map_err(Error::Transceive)?;
+        command::check_response_len(len, max)
+    }
+
+    /// Authenticates to sector using key.
+    ///
+    /// Fails with `AuthenticationRejected` if the tag responds with anything else than empty
+    /// response.
+    pub async fn authenticate_sector<'s, SN: Into<SectorBlockOffset<Cap>>, K: Into<Key>>(&'s mut self, sector_number: SN, key_option: KeyOption, key: K) -> Result<AsyncAuthenticatedSector<'s, T, Cap>, AuthError<T::TransceiveError>> {
+        let sector_offset = sector_number.into();
+        let key = key.into();
+        let (auth_cmd, len) = command::auth(key_option, sector_offset.into(), key.as_bytes(), self.tag.tag_id());
+
+        let mut resp = [0u8; 16];
+        let len = self.transceive_raw(&auth_cmd[..len], &mut resp).await.map_err(AuthError::Transceive)?;
+        if !command::auth_accepted(len) {
+            return Err(AuthError::AuthenticationRejected);
+        }
+
+        Ok(AsyncAuthenticatedSector { tag: self, sector_offset: sector_offset, key_option: key_option })
+    }
+}
+
+/// Reference to authenticated sector of `AsyncMifareTag`.
+///
+/// Borrows the tag the same way `AuthenticatedSector` does, so only one sector can be
+/// authenticated at a time.
+pub struct AsyncAuthenticatedSector<'a, T: 'a, Cap: 'a = Cap4K> {
+    tag: &'a mut AsyncMifareTag<T, Cap>,
+    sector_offset: SectorBlockOffset<Cap>,
+    key_option: KeyOption,
+}
+
+impl<'a, T: 'a + AsyncNFCTag, Cap: 'a + TagCapacity> AsyncAuthenticatedSector<'a, T, Cap> {
+    /// Returns which key was used for authentication.
+    pub fn key_option(&self) -> KeyOption {
+        self.key_option
+    }
+
+    // Converts offset within this sector to absolute one
+    fn absolute_offset(&self, offset: BlockOffset) -> Result<AbsoluteBlockOffset<Cap>, Error<T::TransceiveError>> {
+        self.sector_offset.checked_add(offset).ok_or(Error::BlockOutOfRange(offset))
+    }
+
+    /// Reads 16 bytes of data from given block
+    ///
+    /// Fails with `BufferTooSmall` if `buf` is shorter than 16 bytes and with
+    /// `UnexpectedLength` if the tag doesn't return whole block.
+    pub async fn read_block(&mut self, offset: BlockOffset, buf: &mut [u8]) -> Result<(), Error<T::TransceiveError>> {
+        let offset = self.absolute_offset(offset)?;
+        command::check_read_buffer(buf.len())?;
+        let read_cmd = command::read(offset.into());
+        let len = self.tag.transceive_raw(&read_cmd, &mut buf[..command::READ_RESPONSE_LEN]).await?;
+        command::check_read_len(len)
+    }
+
+    /// Writes 16 bytes of data to given block
+    ///
+    /// WARNING: NOT tested!!! Use at your own risk! By writing incorrect values, you may
+    /// permanently damage the tag!
+    pub async fn write_block(&mut self, offset: BlockOffset, data: &[u8; 16]) -> Result<(), Error<T::TransceiveError>> {
+        let offset = self.absolute_offset(offset)?;
+        let write_cmd = command::write(offset.into(), data);
+        let mut resp = [0; 16];
+        let len = self.tag.transceive_raw(&write_cmd, &mut resp).await?;
+        command::check_ack(&resp[..len])
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+    use crate::{Key, KeyOption, Error, AuthError, BlockOffset, SectorNumber4K};
+    use crate::mock::{MockTag, MockError};
+    use crate::uid::Uid;
+    use super::AsyncMifareTag;
+
+    // MockTag never returns Pending, so polling once is enough and the waker is never used
+    fn block_on<F: Future>(future: F) -> F::Output {
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(core::ptr::null(), &VTABLE)
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+        let waker = unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) };
+        let mut context = Context::from_waker(&waker);
+        match pin!(future).as_mut().poll(&mut context) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("mock tag future is pending"),
+        }
+    }
+
+    #[test]
+    fn write_and_read_block() {
+        let mut tag = AsyncMifareTag::new(MockTag::new(Uid::Single([0x01, 0x02, 0x03, 0x04]))).unwrap();
+        let sector_number = SectorNumber4K::new(1).unwrap();
+        block_on(async {
+            let mut sector = tag.authenticate_sector(sector_number, KeyOption::KeyA, Key::DEFAULT).await.unwrap();
+            assert_eq!(sector.key_option(), KeyOption::KeyA);
+            sector.write_block(BlockOffset::new(2).unwrap(), &[0x42; 16]).await.unwrap();
+
+            let mut buf = [0; 16];
+            sector.read_block(BlockOffset::new(2).unwrap(), &mut buf).await.unwrap();
+            assert_eq!(buf, [0x42; 16]);
+            match sector.read_block(BlockOffset::new(2).unwrap(), &mut buf[..15]).await {
+                Err(Error::BufferTooSmall { len: 15, required: 16 }) => (),
+                result => panic!("unexpected result: {:?}", result),
+            }
+            match sector.read_block(BlockOffset::new(4).unwrap(), &mut buf).await {
+                Err(Error::BlockOutOfRange(_)) => (),
+                result => panic!("unexpected result: {:?}", result),
+            }
+        });
+        // The sector borrowed the tag only until it was dropped
+        assert_eq!(tag.tag.image()[(6 * 16)..(7 * 16)], [0x42; 16]);
+    }
+
+    #[test]
+    fn wrong_key_is_rejected() {
+        let mut mock = MockTag::new(Uid::Single([0x01, 0x02, 0x03, 0x04]));
+        mock.set_key_a(1, [0x12; 6]);
+        let mut tag = AsyncMifareTag::new(mock).unwrap();
+        match block_on(tag.authenticate_sector(SectorNumber4K::new(1).unwrap(), KeyOption::KeyA, Key::DEFAULT)) {
+            Err(AuthError::Transceive(Error::Transceive(MockError::NoResponse))) => (),
+            Err(error) => panic!("unexpected error: {:?}", error),
+            Ok(_) => panic!("authentication succeeded"),
+        }
+        // The tag is halted after failed authentication
+        match block_on(tag.transceive_raw(&[0x30, 0x04], &mut [0; 16])) {
+            Err(Error::Transceive(MockError::NoResponse)) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+// Framing of Mifare Classic commands, shared by blocking and async implementation.
+
+use crate::{KeyOption, Error};
+
+// Authentication command is longest for 7 byte UIDs
+pub const AUTH_MAX_LEN: usize = 15;
+
+// Checks that the reader didn't report longer response than it was allowed to store
+pub fn check_response_len<E>(len: usize, max: usize) -> Result<usize, Error<E>> {
+    if len > max {
+        return Err(Error::ResponseTooLong { len: len, max: max });
+    }
+    Ok(len)
+}
+
+// Builds authentication command. Returns buffer and length of the command in it.
+//
+// `tag_id` must have 4 or 7 bytes.
+pub fn auth(key_option: KeyOption, block: u8, key: &[u8; 6], tag_id: &[u8]) -> ([u8; AUTH_MAX_LEN], usize) {
+    let cmd = match key_option {
+        KeyOption::KeyA => 0x60,
+        KeyOption::KeyB => 0x61,
+    };
+
+    let mut buf = [0; AUTH_MAX_LEN];
+    buf[0] = cmd;
+    buf[1] = block;
+    buf[2..8].copy_from_slice(key);
+    match tag_id.len() {
+        4 | 7 => buf[8..(8 + tag_id.len())].copy_from_slice(tag_id),
+        _ => unreachable!(),
+    }
+    (buf, 8 + tag_id.len())
+}
+
+// Tag responds with empty response on success, NAK otherwise
+pub fn auth_accepted(response_len: usize) -> bool {
+    response_len == 0
+}
+
+pub fn read(block: u8) -> [u8; 2] {
+    [0x30, block]
+}
+
+// Tag responds to read with whole block, anything shorter is NAK or broken response
+pub const READ_RESPONSE_LEN: usize = 16;
+
+// Checks that buffer passed to read can hold whole block
+pub fn check_read_buffer<E>(buf_len: usize) -> Result<(), Error<E>> {
+    if buf_len < READ_RESPONSE_LEN {
+        return Err(Error::BufferTooSmall { len: buf_len, required: READ_RESPONSE_LEN });
+    }
+    Ok(())
+}
+
+// Checks that the tag responded to read with whole block
+pub fn check_read_len<E>(len: usize) -> Result<(), Error<E>> {
+    if len != READ_RESPONSE_LEN {
+        return Err(Error::UnexpectedLength { len: len, expected: READ_RESPONSE_LEN });
+    }
+    Ok(())
+}
+
+pub fn write(block: u8, data: &[u8; 16]) -> [u8; 18] {
+    let mut cmd = [0; 18];
+    cmd[0] = 0xA0;
+    cmd[1] = block;
+    cmd[2..].copy_from_slice(data);
+    cmd
+}
+
+// `cmd` is one of increment (0xC1), decrement (0xC0) or restore (0xC2)
+pub fn value(cmd: u8, block: u8, operand: u32) -> [u8; 6] {
+    [cmd, block, operand as u8, (operand >> 8) as u8, (operand >> 16) as u8, (operand >> 24) as u8]
+}
+
+pub fn transfer(block: u8) -> [u8; 2] {
+    [0xB0, block]
+}
+
+pub const HALT: [u8; 2] = [0x50, 0x00];
+
+// 4 bit acknowledgement of write, value and transfer commands, anything else is NAK
+pub const ACK: u8 = 0x0A;
+
+// Checks response to command answered by ACK/NAK. Readers which handle ACK themselves
+// return empty response, NAK is returned as single byte with the code in lower nibble.
+pub fn check_ack<E>(response: &[u8]) -> Result<(), Error<E>> {
+    match *response {
+        [] => Ok(()),
+        [code] if code & 0x0F == ACK => Ok(()),
+        [code] => Err(Error::NakReceived(code & 0x0F)),
+        _ => Err(Error::UnexpectedResponse { len: response.len() }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ack() {
+        assert!(check_ack::<()>(&[]).is_ok());
+        assert!(check_ack::<()>(&[0x0A]).is_ok());
+        // Only the lower nibble is significant
+        assert!(check_ack::<()>(&[0xFA]).is_ok());
+    }
+
+    #[test]
+    fn nak() {
+        for &code in &[0x0, 0x1, 0x4, 0x5] {
+            match check_ack::<()>(&[code]) {
+                Err(Error::NakReceived(received)) => assert_eq!(received, code),
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn long_response() {
+        match check_ack::<()>(&[0x0A, 0x00]) {
+            Err(Error::UnexpectedResponse { len: 2 }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
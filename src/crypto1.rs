@@ -0,0 +1,156 @@
+// Feedback taps of LFSR split into odd and even bits.
+const LF_POLY_ODD: u32 = 0x29CE5C;
+const LF_POLY_EVEN: u32 = 0x870804;
+
+fn bit(x: u32, n: u32) -> u32 {
+    (x >> n) & 1
+}
+
+// Same as bit(), but with bytes in big endian order
+fn be_bit(x: u32, n: u32) -> u32 {
+    bit(x, n ^ 24)
+}
+
+fn parity(x: u32) -> u32 {
+    x.count_ones() & 1
+}
+
+// Non-linear filter function applied to odd bits of the LFSR
+fn filter(x: u32) -> u32 {
+    let mut f = 0xf22c0 >> (x & 0xf) & 16;
+    f |= 0x6c9c0 >> (x >> 4 & 0xf) & 8;
+    f |= 0x3c8b0 >> (x >> 8 & 0xf) & 4;
+    f |= 0x1e458 >> (x >> 12 & 0xf) & 2;
+    f |= 0x0d938 >> (x >> 16 & 0xf) & 1;
+    bit(0xEC57E80A, f)
+}
+
+/// Returns state of the 16 bit tag PRNG `n` steps after `x`.
+pub fn prng_successor(x: u32, n: u32) -> u32 {
+    let mut x = x.swap_bytes();
+    for _ in 0..n {
+        x = x >> 1 | (x >> 16 ^ x >> 18 ^ x >> 19 ^ x >> 21) << 31;
+    }
+    x.swap_bytes()
+}
+
+/// State of Crypto1 stream cipher.
+///
+/// This follows the published description of the cipher (and the crapto1 implementation),
+/// so it can be used to simulate tags or decrypt captured communication.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Crypto1 {
+    odd: u32,
+    even: u32,
+}
+
+impl Crypto1 {
+    /// Initializes the cipher with 48 bit key. First byte of the key is the most significant.
+    pub fn new(key: u64) -> Self {
+        let mut odd = 0;
+        let mut even = 0;
+        for i in (0..24).rev() {
+            let i = i * 2 + 1;
+            odd = odd << 1 | ((key >> ((i - 1) ^ 7)) & 1) as u32;
+            even = even << 1 | ((key >> (i ^ 7)) & 1) as u32;
+        }
+        Crypto1 { odd: odd, even: even }
+    }
+
+    /// Shifts the LFSR once, feeding in `input`, and returns keystream bit.
+    ///
+    /// If `is_encrypted` is true, `input` is treated as encrypted, so it's decrypted with the
+    /// keystream bit before being fed in.
+    pub fn crypto1_bit(&mut self, input: bool, is_encrypted: bool) -> bool {
+        let ret = filter(self.odd);
+        let mut feedin = ret & is_encrypted as u32;
+        feedin ^= input as u32;
+        feedin ^= LF_POLY_ODD & self.odd;
+        feedin ^= LF_POLY_EVEN & self.even;
+        self.even = self.even << 1 | parity(feedin);
+        ::core::mem::swap(&mut self.odd, &mut self.even);
+        ret != 0
+    }
+
+    /// Shifts the LFSR eight times, feeding in bits of `input` (LSB first), and returns
+    /// keystream byte.
+    pub fn crypto1_byte(&mut self, input: u8, is_encrypted: bool) -> u8 {
+        let mut ret = 0;
+        for i in 0..8 {
+            ret |= (self.crypto1_bit((input >> i) & 1 != 0, is_encrypted) as u8) << i;
+        }
+        ret
+    }
+
+    /// Shifts the LFSR 32 times, feeding in bits of `input` (big endian bytes, LSB first), and
+    /// returns keystream word in the same order.
+    pub fn crypto1_word(&mut self, input: u32, is_encrypted: bool) -> u32 {
+        let mut ret = 0;
+        for i in 0..32 {
+            ret |= (self.crypto1_bit(be_bit(input, i) != 0, is_encrypted) as u32) << (i ^ 24);
+        }
+        ret
+    }
+
+    /// Feeds authentication nonces into the cipher as the tag and reader do.
+    ///
+    /// Returns keystream used to encrypt `nr`. Keystream following it (obtained using
+    /// `keystream_byte()` or `crypto1_word(0, false)`) encrypts the rest of the communication,
+    /// starting with the reader answer.
+    pub fn authenticate(&mut self, uid: u32, nt: u32, nr: u32) -> u32 {
+        self.crypto1_word(uid ^ nt, false);
+        self.crypto1_word(nr, false)
+    }
+
+    /// Returns next keystream byte.
+    pub fn keystream_byte(&mut self) -> u8 {
+        self.crypto1_byte(0, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Authentication with key FFFFFFFFFFFF, as used in the example of mfkey64 (crapto1)
+    const KEY: u64 = 0xFFFFFFFFFFFF;
+    const UID: u32 = 0x9C599B32;
+    const NT: u32 = 0x82A4166C;
+    const NR_ENC: u32 = 0xA1E458CE;
+    const AR_ENC: u32 = 0x6EEA41E0;
+    const AT_ENC: u32 = 0x5CADF439;
+
+    #[test]
+    fn prng_period() {
+        // The tag PRNG is 16 bit LFSR with period 65535
+        assert_eq!(prng_successor(NT, 65535), NT);
+        assert_ne!(prng_successor(NT, 32768), NT);
+    }
+
+    #[test]
+    fn tag_side_authentication() {
+        let mut state = Crypto1::new(KEY);
+        state.crypto1_word(UID ^ NT, false);
+        state.crypto1_word(NR_ENC, true);
+        // Reader answers with suc^64(nt), tag with suc^96(nt)
+        assert_eq!(AR_ENC ^ state.crypto1_word(0, false), prng_successor(NT, 64));
+        assert_eq!(AT_ENC ^ state.crypto1_word(0, false), prng_successor(NT, 96));
+    }
+
+    #[test]
+    fn reader_side_authentication() {
+        let mut tag = Crypto1::new(KEY);
+        tag.crypto1_word(UID ^ NT, false);
+        let nr = NR_ENC ^ tag.crypto1_word(NR_ENC, true);
+
+        let mut reader = Crypto1::new(KEY);
+        assert_eq!(reader.authenticate(UID, NT, nr) ^ nr, NR_ENC);
+        assert_eq!(reader, tag);
+
+        // Keystream bytes encrypt the answer in big endian order
+        let ar = prng_successor(NT, 64);
+        for (i, byte) in AR_ENC.to_be_bytes().iter().enumerate() {
+            assert_eq!(byte ^ reader.keystream_byte(), (ar >> (24 - i * 8)) as u8);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use aes::Aes128;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::cipher::generic_array::GenericArray;
+use crate::Key;
+
+// Prepended to diversification input when deriving AES-128 key
+const DIV_CONSTANT_AES128: u8 = 0x01;
+// Constant used when generating CMAC subkeys of 128 bit block cipher
+const CMAC_RB: u8 = 0x87;
+// Diversification input is padded to two AES blocks
+const INPUT_LEN: usize = 32;
+
+/// Maximum total length of `uid` and `aid` passed to `diversify_key()`.
+pub const MAX_INPUT_LEN: usize = INPUT_LEN - 1;
+
+// Multiplies block by x in GF(2^128) (left shift by one bit)
+fn shift_left(block: &[u8; 16]) -> [u8; 16] {
+    let mut result = [0; 16];
+    for i in 0..16 {
+        let carry = if i < 15 { block[i + 1] >> 7 } else { 0 };
+        result[i] = block[i] << 1 | carry;
+    }
+    if block[0] & 0x80 != 0 {
+        result[15] ^= CMAC_RB;
+    }
+    result
+}
+
+fn encrypt(cipher: &Aes128, block: &mut [u8; 16]) {
+    let mut buf = GenericArray::clone_from_slice(block);
+    cipher.encrypt_block(&mut buf);
+    block.copy_from_slice(&buf);
+}
+
+/// Derives AES-128 key from `master` key and diversification input `data` as described in
+/// NXP AN10922 (CMAC with diversification constant 0x01).
+///
+/// Panics if `data` is longer than `MAX_INPUT_LEN` bytes.
+pub fn diversify_aes128(master: &[u8; 16], data: &[u8]) -> [u8; 16] {
+    assert!(data.len() <= MAX_INPUT_LEN, "diversification input too long");
+
+    let cipher = Aes128::new(GenericArray::from_slice(master));
+
+    // Subkeys K1 and K2 (RFC 4493)
+    let mut l = [0; 16];
+    encrypt(&cipher, &mut l);
+    let k1 = shift_left(&l);
+    let k2 = shift_left(&k1);
+
+    let mut input = [0; INPUT_LEN];
+    input[0] = DIV_CONSTANT_AES128;
+    input[1..(data.len() + 1)].copy_from_slice(data);
+    // Incomplete input is padded and the last block is masked with K2 instead of K1
+    let subkey = if data.len() + 1 < INPUT_LEN {
+        input[data.len() + 1] = 0x80;
+        k2
+    } else {
+        k1
+    };
+    for (byte, mask) in input[16..].iter_mut().zip(subkey.iter()) {
+        *byte ^= *mask;
+    }
+
+    // CBC with zero IV, the last block is the MAC
+    let mut mac = [0; 16];
+    for block in input.chunks(16) {
+        for (byte, input) in mac.iter_mut().zip(block) {
+            *byte ^= *input;
+        }
+        encrypt(&cipher, &mut mac);
+    }
+    mac
+}
+
+/// Derives Mifare Classic key of card with `uid` from 16 byte `master` key.
+///
+/// The diversification input is `uid` followed by `aid` (which may be empty or additionally
+/// contain system identifier, e.g. `AID || SystemIdentifier` as in AN10922). The key is
+/// AES-128 key derived according to AN10922 (see `diversify_aes128()`), truncated to the
+/// first 6 bytes.
+///
+/// The truncation is specific to this crate - AN10922 doesn't define diversification of
+/// Mifare Classic keys this way, so the keys are NOT interoperable with NXP's or reader
+/// vendors' Classic key diversification. Use it only for cards that are also personalized
+/// using this function.
+///
+/// Since `Key` implements `KeyProvider`, the result can be passed directly to
+/// `MifareTag::dump()` and similar functions.
+///
+/// Panics if `master` doesn't have 16 bytes or `uid` and `aid` together are longer than
+/// `MAX_INPUT_LEN` bytes.
+pub fn diversify_key(master: &[u8], uid: &[u8], aid: &[u8]) -> Key {
+    assert_eq!(master.len(), 16, "master key must have 16 bytes");
+    assert!(uid.len() + aid.len() <= MAX_INPUT_LEN, "diversification input too long");
+
+    let mut master_key = [0; 16];
+    master_key.copy_from_slice(master);
+    let mut data = [0; MAX_INPUT_LEN];
+    data[..uid.len()].copy_from_slice(uid);
+    data[uid.len()..(uid.len() + aid.len())].copy_from_slice(aid);
+
+    let diversified = diversify_aes128(&master_key, &data[..(uid.len() + aid.len())]);
+    let mut key = [0; 6];
+    key.copy_from_slice(&diversified[..6]);
+    Key::new(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Subkey generation example from RFC 4493
+    #[test]
+    fn cmac_subkeys() {
+        let key = [0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7, 0x15, 0x88, 0x09, 0xCF, 0x4F, 0x3C];
+        let cipher = Aes128::new(GenericArray::from_slice(&key));
+        let mut l = [0; 16];
+        encrypt(&cipher, &mut l);
+        assert_eq!(l, [0x7D, 0xF7, 0x6B, 0x0C, 0x1A, 0xB8, 0x99, 0xB3, 0x3E, 0x42, 0xF0, 0x47, 0xB9, 0x1B, 0x54, 0x6F]);
+        let k1 = shift_left(&l);
+        assert_eq!(k1, [0xFB, 0xEE, 0xD6, 0x18, 0x35, 0x71, 0x33, 0x66, 0x7C, 0x85, 0xE0, 0x8F, 0x72, 0x36, 0xA8, 0xDE]);
+        assert_eq!(shift_left(&k1), [0xF7, 0xDD, 0xAC, 0x30, 0x6A, 0xE2, 0x66, 0xCC, 0xF9, 0x0B, 0xC1, 0x1E, 0xE4, 0x6D, 0x51, 0x3B]);
+    }
+
+    const MASTER: [u8; 16] = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF];
+    const UID: [u8; 7] = [0x04, 0x78, 0x2E, 0x21, 0x80, 0x1D, 0x80];
+    // AID 3042F5 followed by system identifier "NXP Abu"
+    const AID: [u8; 10] = [0x30, 0x42, 0xF5, 0x4E, 0x58, 0x50, 0x20, 0x41, 0x62, 0x75];
+    const DIVERSIFIED: [u8; 16] = [0xA8, 0xDD, 0x63, 0xA3, 0xB8, 0x9D, 0x54, 0xB3, 0x7C, 0xA8, 0x02, 0x47, 0x3F, 0xDA, 0x91, 0x75];
+
+    // AES-128 key diversification example from AN10922
+    #[test]
+    fn an10922_example() {
+        let mut data = [0; 17];
+        data[..7].copy_from_slice(&UID);
+        data[7..].copy_from_slice(&AID);
+        assert_eq!(diversify_aes128(&MASTER, &data), DIVERSIFIED);
+    }
+
+    #[test]
+    fn classic_key_is_truncated() {
+        let key = diversify_key(&MASTER, &UID, &AID);
+        assert_eq!(key.as_bytes(), &DIVERSIFIED[..6]);
+    }
+}
@@ -0,0 +1,621 @@
+use core::fmt;
+#[cfg(feature = "std")]
+use std::vec::Vec;
+use crate::{NFCTag, MifareTag, AuthenticatedSector, KeyProvider, KeyOption, Error, AuthError, BlockOffset};
+use crate::mad::MadError;
+use crate::access::AccessBits;
+use crate::numerics::{TagCapacity, SectorNumber, SectorNumber4K, SectorBlockOffset, AbsoluteBlockOffset};
+
+/// Error returned by operations working with whole tag or multiple sectors.
+#[derive(Debug)]
+pub enum DumpError<E> {
+    /// Authentication to sector failed.
+    Authentication { sector: SectorNumber4K, error: AuthError<E> },
+    /// Reading or writing of sector failed.
+    Transfer { sector: SectorNumber4K, error: Error<E> },
+    /// Key provider doesn't know the key of sector.
+    MissingKey { sector: SectorNumber4K, key_option: KeyOption },
+    /// Image has `len` bytes, but the tag has `expected` bytes.
+    InvalidImageSize { len: usize, expected: usize },
+    /// Range of `len` bytes doesn't fit into the tag.
+    OutOfRange { len: usize },
+    /// Range contains trailer of `sector`, which was rejected by `TrailerHandling::Reject`.
+    TrailerInRange { sector: SectorNumber4K },
+    /// Trailer of `sector` in the image contains zeros in place of key A, see
+    /// `RestoreOptions::allow_zero_key_a`.
+    ZeroKeyA { sector: SectorNumber4K },
+    /// MAD read from the tag is invalid.
+    InvalidMad(MadError),
+}
+
+impl<E: fmt::Display> fmt::Display for DumpError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DumpError::Authentication { sector, ref error } => write!(f, "authentication to sector {} failed: {}", u8::from(sector), error),
+            DumpError::Transfer { sector, ref error } => write!(f, "transfer of sector {} failed: {}", u8::from(sector), error),
+            DumpError::MissingKey { sector, key_option } => write!(f, "{:?} of sector {} isn't known", key_option, u8::from(sector)),
+            DumpError::InvalidImageSize { len, expected } => write!(f, "image has {} bytes, but the tag has {} bytes", len, expected),
+            DumpError::OutOfRange { len } => write!(f, "range of {} bytes doesn't fit into the tag", len),
+            DumpError::TrailerInRange { sector } => write!(f, "range contains trailer of sector {}", u8::from(sector)),
+            DumpError::ZeroKeyA { sector } => write!(f, "image contains zero key A of sector {}", u8::from(sector)),
+            DumpError::InvalidMad(ref error) => write!(f, "invalid MAD: {}", error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: ::std::error::Error + 'static> ::std::error::Error for DumpError<E> {
+    fn source(&self) -> Option<&(dyn ::std::error::Error + 'static)> {
+        match *self {
+            DumpError::Authentication { ref error, .. } => Some(error),
+            DumpError::Transfer { ref error, .. } => Some(error),
+            DumpError::InvalidMad(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// Converts sector number of any tag into the widest one, used in errors
+fn widen<Cap: TagCapacity>(sector: SectorNumber<Cap>) -> SectorNumber4K {
+    SectorNumber4K::new(sector.into()).expect("invalid sector number")
+}
+
+/// Options of `MifareTag::restore()`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub struct RestoreOptions {
+    /// Write manufacturer block (block 0). Genuine tags refuse to write it.
+    pub write_block0: bool,
+    /// Write sector trailers. Note that key A isn't readable, so dumps usually contain zeros
+    /// in its place.
+    pub write_trailers: bool,
+    /// Allow writing trailers containing key A 000000000000. Without it, such trailers are
+    /// refused, because they usually come from dumps and would replace the real key A.
+    pub allow_zero_key_a: bool,
+}
+
+/// Handling of sector trailers by `MifareTag::read_bytes()` and `MifareTag::write_bytes()`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TrailerHandling {
+    /// Trailers are skipped, so the range consists of data blocks only.
+    Skip,
+    /// Trailers are read or written as any other block.
+    Include,
+    /// Range containing a trailer is rejected with `TrailerInRange` before accessing the tag.
+    Reject,
+}
+
+// Checks that range of `len` bytes starting at `start` fits into the tag and doesn't contain
+// rejected trailers
+fn check_range<Cap: TagCapacity, E>(start: AbsoluteBlockOffset<Cap>, len: usize, trailers: TrailerHandling) -> Result<(), DumpError<E>> {
+    let mut remaining = len;
+    let mut block = u8::from(start) as u16;
+    while remaining > 0 {
+        if block >= Cap::max_blocks() {
+            return Err(DumpError::OutOfRange { len: len });
+        }
+        let offset = AbsoluteBlockOffset::<Cap>::new(block as u8).expect("invalid block offset");
+        block += 1;
+
+        let sector_offset = offset.sector_offset();
+        if u8::from(offset) == u8::from(sector_offset.sector_trailer()) {
+            match trailers {
+                TrailerHandling::Skip => continue,
+                TrailerHandling::Include => (),
+                TrailerHandling::Reject => return Err(DumpError::TrailerInRange { sector: widen(sector_offset.into()) }),
+            }
+        }
+        remaining = remaining.saturating_sub(16);
+    }
+    Ok(())
+}
+
+// Checks that trailers in the image can be written safely, see `MifareTag::restore()`
+fn check_trailers<Cap: TagCapacity, E>(image: &[u8], key_option: KeyOption, options: RestoreOptions) -> Result<(), DumpError<E>> {
+    for sector in SectorNumber::<Cap>::all() {
+        let sector_number = widen(sector);
+        let trailer = u8::from(SectorBlockOffset::from(sector).sector_trailer()) as usize * 16;
+        let mut access_bytes = [0; 4];
+        access_bytes.copy_from_slice(&image[(trailer + 6)..(trailer + 10)]);
+
+        let access_bits = AccessBits::from_bytes(&access_bytes).map_err(|error| DumpError::Transfer { sector: sector_number, error: Error::InvalidAccessBits(error) })?;
+        if !access_bits.trailer().access_bits_writable(key_option) {
+            return Err(DumpError::Transfer { sector: sector_number, error: Error::WouldLockOut });
+        }
+        if image[trailer..(trailer + 6)] == [0; 6] && !options.allow_zero_key_a {
+            return Err(DumpError::ZeroKeyA { sector: sector_number });
+        }
+    }
+    Ok(())
+}
+
+impl<T: NFCTag, Cap: TagCapacity> MifareTag<T, Cap> {
+    /// Reads all blocks of the tag, including sector trailers.
+    ///
+    /// Returns image of the tag in the layout used by `nfc-mfclassic` and `mfoc` - all blocks
+    /// in order of their addresses. Size of the image is given by capacity of the tag.
+    ///
+    /// Sectors are authenticated using `key_option` with keys supplied by `keys` (pass a
+    /// `Key` to use the same key for all sectors). If `fill_unreadable` is `Some`, sectors
+    /// which have no known key or can't be authenticated or read are filled with given byte
+    /// instead of failing. The tag is selected again after such failure (see `reselect()`),
+    /// so that the remaining sectors can be read. If the backend can't select the tag
+    /// again, this fails with `Transfer` containing `Error::ReselectUnsupported` instead.
+    /// If the backend can't use `key_option` at all, this fails with `Authentication`
+    /// containing `AuthError::KeyUnsupported` regardless of `fill_unreadable`.
+    #[cfg(feature = "std")]
+    pub fn dump<P: KeyProvider>(&mut self, key_option: KeyOption, keys: &P, fill_unreadable: Option<u8>) -> Result<Vec<u8>, DumpError<T::TransceiveError>> {
+        let mut image = vec![0; Cap::bytes() as usize];
+        for sector in SectorNumber::<Cap>::all() {
+            let sector_offset = SectorBlockOffset::from(sector);
+            let start = u8::from(sector_offset) as usize * 16;
+            let end = start + sector_offset.block_count() as usize * 16;
+
+            match self.dump_sector(sector, key_option, keys, &mut image[start..end]) {
+                Ok(()) => (),
+                // Other sectors can't be read either
+                Err(error @ DumpError::Authentication { error: AuthError::KeyUnsupported(_), .. }) => return Err(error),
+                Err(error) => {
+                    let fill = match fill_unreadable {
+                        Some(fill) => fill,
+                        None => return Err(error),
+                    };
+                    for byte in &mut image[start..end] {
+                        *byte = fill;
+                    }
+
+                    // Failed authentication or read leaves the tag unresponsive
+                    if !matches!(error, DumpError::MissingKey { .. }) {
+                        self.reselect().map_err(|error| DumpError::Transfer { sector: widen(sector), error: error })?;
+                    }
+                },
+            }
+        }
+        Ok(image)
+    }
+
+    // Authenticates to sector using key supplied by `keys` and reads all its blocks
+    pub(crate) fn dump_sector<P: KeyProvider>(&mut self, sector: SectorNumber<Cap>, key_option: KeyOption, keys: &P, buf: &mut [u8]) -> Result<(), DumpError<T::TransceiveError>> {
+        let sector_number = widen(sector);
+        let mut authenticated = self.authenticate_from(sector, key_option, keys)?;
+        authenticated.read_sector(buf, true).map_err(|error| DumpError::Transfer { sector: sector_number, error: error })?;
+        Ok(())
+    }
+
+    // Authenticates to sector using key supplied by `keys`
+    fn authenticate_from<'s, P: KeyProvider>(&'s mut self, sector: SectorNumber<Cap>, key_option: KeyOption, keys: &P) -> Result<AuthenticatedSector<'s, T, Cap>, DumpError<T::TransceiveError>> {
+        let sector_number = widen(sector);
+        let key = keys.key(sector_number, key_option).ok_or(DumpError::MissingKey { sector: sector_number, key_option: key_option })?;
+        self.authenticate_sector(sector, key_option, key).map_err(|error| DumpError::Authentication { sector: sector_number, error: error })
+    }
+
+    /// Writes image of the tag (as produced by `dump()`) back to the tag.
+    ///
+    /// Sectors are authenticated using `key_option` with keys supplied by `keys` (pass a
+    /// `Key` to use the same key for all sectors). Block 0 and sector trailers are skipped
+    /// unless enabled in `options`.
+    ///
+    /// If trailers are written, all of them are checked before writing anything: trailer
+    /// with invalid access bits fails with `Transfer` containing `Error::InvalidAccessBits`,
+    /// trailer whose access bits couldn't be changed later using `key_option` with
+    /// `Error::WouldLockOut` and trailer with zero key A with `ZeroKeyA`, unless allowed in
+    /// `options`.
+    ///
+    /// WARNING: NOT tested!!! Use at your own risk! By writing incorrect values, you may
+    /// permanently damage the tag!
+    pub fn restore<P: KeyProvider>(&mut self, image: &[u8], key_option: KeyOption, keys: &P, options: RestoreOptions) -> Result<(), DumpError<T::TransceiveError>> {
+        if image.len() != Cap::bytes() as usize {
+            return Err(DumpError::InvalidImageSize { len: image.len(), expected: Cap::bytes() as usize });
+        }
+        if options.write_trailers {
+            check_trailers::<Cap, T::TransceiveError>(image, key_option, options)?;
+        }
+
+        for sector in SectorNumber::<Cap>::all() {
+            let sector_offset = SectorBlockOffset::from(sector);
+            let start = u8::from(sector_offset) as usize * 16;
+            let block_count = sector_offset.block_count();
+
+            let sector_number = widen(sector);
+            let mut authenticated = self.authenticate_from(sector, key_option, keys)?;
+            for block in 0..block_count {
+                let mut data = [0; 16];
+                data.copy_from_slice(&image[(start + block as usize * 16)..(start + block as usize * 16 + 16)]);
+
+                let result = if block == block_count - 1 {
+                    if !options.write_trailers {
+                        continue;
+                    }
+                    authenticated.write_keys(&data)
+                } else {
+                    if start == 0 && block == 0 && !options.write_block0 {
+                        continue;
+                    }
+                    // Always valid, because data blocks have offsets at most 14
+                    authenticated.write_block(BlockOffset::new(block).expect("invalid block offset"), &data)
+                };
+                result.map_err(|error| DumpError::Transfer { sector: sector_number, error: error })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads `buf.len()` bytes starting at block `start`, which may span multiple sectors.
+    ///
+    /// Sectors are authenticated as needed using `key_option` with keys supplied by `keys`.
+    /// Sector trailers in the range are handled according to `trailers`. The range is
+    /// checked before reading anything.
+    pub fn read_bytes<P: KeyProvider>(&mut self, start: AbsoluteBlockOffset<Cap>, key_option: KeyOption, keys: &P, trailers: TrailerHandling, buf: &mut [u8]) -> Result<(), DumpError<T::TransceiveError>> {
+        check_range::<Cap, T::TransceiveError>(start, buf.len(), trailers)?;
+
+        let mut sector_offset = start.sector_offset();
+        let mut first = u8::from(start) - u8::from(sector_offset);
+        let mut pos = 0;
+        while pos < buf.len() {
+            let sector_number = widen(sector_offset.into());
+            let block_count = sector_offset.block_count();
+            let mut authenticated = self.authenticate_from(sector_offset.into(), key_option, keys)?;
+            for offset in first..block_count {
+                if pos == buf.len() {
+                    break;
+                }
+                if offset == block_count - 1 && trailers == TrailerHandling::Skip {
+                    continue;
+                }
+
+                // Range was checked above
+                let block = AbsoluteBlockOffset::new(u8::from(sector_offset) + offset).expect("invalid block offset");
+                let mut data = [0; 16];
+                authenticated.read_block_raw(block, &mut data).map_err(|error| DumpError::Transfer { sector: sector_number, error: error })?;
+                let len = ::core::cmp::min(16, buf.len() - pos);
+                buf[pos..(pos + len)].copy_from_slice(&data[..len]);
+                pos += len;
+            }
+
+            if pos < buf.len() {
+                // Range was checked above, so the next sector exists
+                sector_offset = SectorBlockOffset::new(u8::from(sector_offset) + block_count).expect("invalid sector offset");
+                first = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes `data` starting at block `start`, which may span multiple sectors.
+    ///
+    /// Sectors are authenticated as needed using `key_option` with keys supplied by `keys`.
+    /// Sector trailers in the range are handled according to `trailers`. The range is
+    /// checked before writing anything. If `data` doesn't end at block boundary, the rest
+    /// of the last block is preserved.
+    ///
+    /// WARNING: NOT tested!!! Use at your own risk! By writing incorrect values, you may
+    /// permanently damage the tag! Using `TrailerHandling::Include` is especially dangerous.
+    pub fn write_bytes<P: KeyProvider>(&mut self, start: AbsoluteBlockOffset<Cap>, key_option: KeyOption, keys: &P, trailers: TrailerHandling, data: &[u8]) -> Result<(), DumpError<T::TransceiveError>> {
+        check_range::<Cap, T::TransceiveError>(start, data.len(), trailers)?;
+
+        let mut sector_offset = start.sector_offset();
+        let mut first = u8::from(start) - u8::from(sector_offset);
+        let mut pos = 0;
+        while pos < data.len() {
+            let sector_number = widen(sector_offset.into());
+            let block_count = sector_offset.block_count();
+            let mut authenticated = self.authenticate_from(sector_offset.into(), key_option, keys)?;
+            for offset in first..block_count {
+                if pos == data.len() {
+                    break;
+                }
+                if offset == block_count - 1 && trailers == TrailerHandling::Skip {
+                    continue;
+                }
+
+                // Range was checked above
+                let block = AbsoluteBlockOffset::new(u8::from(sector_offset) + offset).expect("invalid block offset");
+                let len = ::core::cmp::min(16, data.len() - pos);
+                let mut block_data = [0; 16];
+                if len < 16 {
+                    authenticated.read_block_raw(block, &mut block_data).map_err(|error| DumpError::Transfer { sector: sector_number, error: error })?;
+                }
+                block_data[..len].copy_from_slice(&data[pos..(pos + len)]);
+                authenticated.write_block_raw(block, &block_data).map_err(|error| DumpError::Transfer { sector: sector_number, error: error })?;
+                pos += len;
+            }
+
+            if pos < data.len() {
+                // Range was checked above, so the next sector exists
+                sector_offset = SectorBlockOffset::new(u8::from(sector_offset) + block_count).expect("invalid sector offset");
+                first = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::{Key, Uid, AccessCondition};
+    use crate::mock::{MockTag, MockError};
+
+    fn mock_tag() -> MockTag {
+        MockTag::new(Uid::Single([0x01, 0x02, 0x03, 0x04]))
+    }
+
+    // Tag which can't be selected again
+    struct NoSelectTag(MockTag);
+
+    impl NFCTag for NoSelectTag {
+        type TransceiveError = MockError;
+
+        fn tag_id(&self) -> &[u8] {
+            NFCTag::tag_id(&self.0)
+        }
+
+        fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Self::TransceiveError> {
+            NFCTag::transceive(&mut self.0, data_to_tag, data_from_tag)
+        }
+    }
+
+    // Tag counting commands sent to it
+    struct CountingTag {
+        tag: MockTag,
+        count: usize,
+    }
+
+    impl NFCTag for CountingTag {
+        type TransceiveError = MockError;
+
+        fn tag_id(&self) -> &[u8] {
+            NFCTag::tag_id(&self.tag)
+        }
+
+        fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Self::TransceiveError> {
+            self.count += 1;
+            NFCTag::transceive(&mut self.tag, data_to_tag, data_from_tag)
+        }
+    }
+
+    // Tag whose backend can authenticate only with key A
+    struct KeyATag(MockTag);
+
+    impl NFCTag for KeyATag {
+        type TransceiveError = MockError;
+
+        fn tag_id(&self) -> &[u8] {
+            NFCTag::tag_id(&self.0)
+        }
+
+        fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Self::TransceiveError> {
+            NFCTag::transceive(&mut self.0, data_to_tag, data_from_tag)
+        }
+
+        fn supports_key(&self, key_option: KeyOption) -> bool {
+            key_option == KeyOption::KeyA
+        }
+
+        fn select(&mut self) -> Option<Result<(), Self::TransceiveError>> {
+            self.0.select()
+        }
+    }
+
+    // Tag with first byte of each data block set to its number
+    fn numbered_tag() -> MockTag {
+        let mut mock = mock_tag();
+        for block in 1..256 {
+            let is_trailer = if block < 128 { block % 4 == 3 } else { block % 16 == 15 };
+            if !is_trailer {
+                mock.image_mut()[block * 16] = block as u8;
+            }
+        }
+        mock
+    }
+
+    fn block(block: u8) -> AbsoluteBlockOffset<crate::Cap4K> {
+        AbsoluteBlockOffset::new(block).unwrap()
+    }
+
+    #[test]
+    fn read_bytes_across_sectors() {
+        let mut tag = MifareTag::new(numbered_tag()).unwrap();
+        let mut buf = [0; 48];
+        tag.read_bytes(block(2), KeyOption::KeyA, &Key::DEFAULT, TrailerHandling::Skip, &mut buf).unwrap();
+        assert_eq!([buf[0], buf[16], buf[32]], [2, 4, 5]);
+
+        // Into the first large sector
+        tag.read_bytes(block(126), KeyOption::KeyA, &Key::DEFAULT, TrailerHandling::Skip, &mut buf).unwrap();
+        assert_eq!([buf[0], buf[16], buf[32]], [126, 128, 129]);
+
+        tag.read_bytes(block(126), KeyOption::KeyA, &Key::DEFAULT, TrailerHandling::Include, &mut buf).unwrap();
+        assert_eq!([buf[0], buf[32]], [126, 128]);
+        // Trailer as read from the tag, key A reads as zeros
+        assert_eq!(buf[16..22], [0; 6]);
+        assert_eq!(buf[22..26], AccessBits::TRANSPORT.to_bytes());
+
+        // Partial block at the end
+        let mut buf = [0; 17];
+        tag.read_bytes(block(142), KeyOption::KeyA, &Key::DEFAULT, TrailerHandling::Skip, &mut buf).unwrap();
+        assert_eq!([buf[0], buf[16]], [142, 144]);
+    }
+
+    #[test]
+    fn write_bytes_across_sectors() {
+        let mut tag = MifareTag::new(numbered_tag()).unwrap();
+        let data = [0xAA; 48];
+        tag.write_bytes(block(126), KeyOption::KeyA, &Key::DEFAULT, TrailerHandling::Skip, &data).unwrap();
+        let image = tag.tag.image();
+        assert_eq!(image[(126 * 16)..(127 * 16)], [0xAA; 16]);
+        assert_eq!(image[(127 * 16)..(127 * 16 + 6)], [0xFF; 6]);
+        assert_eq!(image[(128 * 16)..(130 * 16)], [0xAA; 32]);
+        assert_eq!(image[130 * 16], 130);
+    }
+
+    #[test]
+    fn write_bytes_keeps_rest_of_last_block() {
+        let mut tag = MifareTag::new(numbered_tag()).unwrap();
+        tag.write_bytes(block(4), KeyOption::KeyA, &Key::DEFAULT, TrailerHandling::Skip, &[0xAA; 20]).unwrap();
+        let image = tag.tag.image();
+        assert_eq!(image[64..84], [0xAA; 20]);
+        assert_eq!(image[84..96], [0; 12]);
+        assert_eq!(image[96], 6);
+    }
+
+    #[test]
+    fn write_bytes_includes_trailer() {
+        let mut tag = MifareTag::new(numbered_tag()).unwrap();
+        let mut data = [0xAA; 32];
+        data[16..22].copy_from_slice(&[0x11; 6]);
+        data[22..26].copy_from_slice(&AccessBits::TRANSPORT.to_bytes());
+        data[26..32].copy_from_slice(&[0x22; 6]);
+        tag.write_bytes(block(6), KeyOption::KeyA, &Key::DEFAULT, TrailerHandling::Include, &data).unwrap();
+        let image = tag.tag.image();
+        assert_eq!(image[96..112], [0xAA; 16]);
+        assert_eq!(image[112..128], data[16..]);
+    }
+
+    #[test]
+    fn rejected_ranges_dont_access_tag() {
+        let mut tag = MifareTag::new(CountingTag { tag: numbered_tag(), count: 0 }).unwrap();
+        let mut buf = [0; 32];
+        match tag.read_bytes(block(126), KeyOption::KeyA, &Key::DEFAULT, TrailerHandling::Reject, &mut buf) {
+            Err(DumpError::TrailerInRange { sector }) => assert_eq!(u8::from(sector), 31),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match tag.write_bytes(block(2), KeyOption::KeyA, &Key::DEFAULT, TrailerHandling::Reject, &buf) {
+            Err(DumpError::TrailerInRange { sector }) => assert_eq!(u8::from(sector), 0),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        // Two data blocks fit before the trailer
+        tag.read_bytes(block(1), KeyOption::KeyA, &Key::DEFAULT, TrailerHandling::Reject, &mut buf).unwrap();
+        assert_eq!([buf[0], buf[16]], [1, 2]);
+        let count = tag.tag.count;
+        assert!(count > 0);
+
+        // The last block is trailer
+        match tag.read_bytes(block(254), KeyOption::KeyA, &Key::DEFAULT, TrailerHandling::Skip, &mut buf) {
+            Err(DumpError::OutOfRange { len: 32 }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match tag.write_bytes(block(255), KeyOption::KeyA, &Key::DEFAULT, TrailerHandling::Include, &buf) {
+            Err(DumpError::OutOfRange { len: 32 }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(tag.tag.count, count);
+
+        let mut tag = MifareTag::new_1k(CountingTag { tag: numbered_tag(), count: 0 }).unwrap();
+        match tag.read_bytes(AbsoluteBlockOffset::new(63).unwrap(), KeyOption::KeyA, &Key::DEFAULT, TrailerHandling::Include, &mut buf) {
+            Err(DumpError::OutOfRange { len: 32 }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(tag.tag.count, 0);
+    }
+
+    #[test]
+    fn dump_fills_unreadable_sectors() {
+        let mut mock = mock_tag();
+        mock.set_key_a(1, [0x11; 6]);
+        mock.image_mut()[128] = 0x22;
+        let mut tag = MifareTag::new_1k(mock).unwrap();
+        let image = tag.dump(KeyOption::KeyA, &Key::DEFAULT, Some(0xEE)).unwrap();
+        assert_eq!(image.len(), 1024);
+        assert_eq!(image[64..128], [0xEE; 64]);
+        assert_eq!(image[128], 0x22);
+    }
+
+    // Image as read by dump(), with key A of each sector replaced by `key_a`
+    fn with_key_a(mut image: Vec<u8>, key_a: [u8; 6]) -> Vec<u8> {
+        for trailer in (0..16).map(|sector| sector * 64 + 48) {
+            image[trailer..(trailer + 6)].copy_from_slice(&key_a);
+        }
+        image
+    }
+
+    #[test]
+    fn dump_and_restore_round_trip() {
+        let mut source = mock_tag();
+        for (i, byte) in source.image_mut()[16..48].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        source.image_mut()[1000] = 0x42;
+        source.set_key_b(2, [0x22; 6]);
+        let mut tag = MifareTag::new_1k(source).unwrap();
+        let image = tag.dump(KeyOption::KeyA, &Key::DEFAULT, None).unwrap();
+
+        let mut tag = MifareTag::new_1k(MockTag::new(Uid::Single([0x05, 0x06, 0x07, 0x08]))).unwrap();
+        let options = RestoreOptions { write_block0: false, write_trailers: true, allow_zero_key_a: false };
+        let image_with_keys = with_key_a(image.clone(), [0xFF; 6]);
+        tag.restore(&image_with_keys, KeyOption::KeyA, &Key::DEFAULT, options).unwrap();
+
+        let restored = tag.tag.image();
+        // Block 0 was skipped
+        assert_eq!(restored[..4], [0x05, 0x06, 0x07, 0x08]);
+        assert_eq!(restored[16..1024], image_with_keys[16..]);
+        assert_eq!(restored[(128 + 48 + 10)..(128 + 64)], [0x22; 6]);
+        assert_eq!(tag.dump(KeyOption::KeyA, &Key::DEFAULT, None).unwrap()[16..], image[16..]);
+    }
+
+    #[test]
+    fn restore_refuses_zero_key_a() {
+        let mut tag = MifareTag::new_1k(mock_tag()).unwrap();
+        let mut image = tag.dump(KeyOption::KeyA, &Key::DEFAULT, None).unwrap();
+        image[64] = 0x11;
+        let options = RestoreOptions { write_block0: false, write_trailers: true, allow_zero_key_a: false };
+        match tag.restore(&image, KeyOption::KeyA, &Key::DEFAULT, options) {
+            Err(DumpError::ZeroKeyA { sector }) => assert_eq!(u8::from(sector), 0),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        // Nothing was written
+        assert_eq!(tag.tag.image()[64], 0);
+        assert_eq!(tag.tag.image()[48..54], [0xFF; 6]);
+
+        // Trailers are checked only if they are written
+        let options = RestoreOptions { write_trailers: false, ..options };
+        tag.restore(&image, KeyOption::KeyA, &Key::DEFAULT, options).unwrap();
+        assert_eq!(tag.tag.image()[64], 0x11);
+        assert_eq!(tag.tag.image()[48..54], [0xFF; 6]);
+
+        let options = RestoreOptions { write_trailers: true, allow_zero_key_a: true, ..options };
+        tag.restore(&image, KeyOption::KeyA, &Key::DEFAULT, options).unwrap();
+        assert_eq!(tag.tag.image()[48..54], [0; 6]);
+    }
+
+    #[test]
+    fn restore_refuses_lockout() {
+        let mut tag = MifareTag::new_1k(mock_tag()).unwrap();
+        let mut image = with_key_a(tag.dump(KeyOption::KeyA, &Key::DEFAULT, None).unwrap(), [0xFF; 6]);
+        image[64] = 0x11;
+        // Access bits of sector 3 writable only with key B
+        let access_bits = AccessBits::new([AccessCondition::from_bits(0).unwrap(); 3], AccessCondition::from_bits(0b011).unwrap(), 0x69);
+        image[(240 + 6)..(240 + 10)].copy_from_slice(&access_bits.to_bytes());
+        let options = RestoreOptions { write_block0: false, write_trailers: true, allow_zero_key_a: false };
+        match tag.restore(&image, KeyOption::KeyA, &Key::DEFAULT, options) {
+            Err(DumpError::Transfer { sector, error: Error::WouldLockOut }) => assert_eq!(u8::from(sector), 3),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        image[240 + 6] ^= 0x01;
+        match tag.restore(&image, KeyOption::KeyA, &Key::DEFAULT, options) {
+            Err(DumpError::Transfer { sector, error: Error::InvalidAccessBits(_) }) => assert_eq!(u8::from(sector), 3),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(tag.tag.image()[64], 0);
+    }
+
+    #[test]
+    fn dump_fails_without_reselect() {
+        let mut mock = mock_tag();
+        mock.set_key_a(1, [0x11; 6]);
+        let mut tag = MifareTag::new_1k(NoSelectTag(mock)).unwrap();
+        match tag.dump(KeyOption::KeyA, &Key::DEFAULT, Some(0xEE)) {
+            Err(DumpError::Transfer { sector, error: Error::ReselectUnsupported }) => assert_eq!(u8::from(sector), 1),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn dump_fails_with_unsupported_key() {
+        let mut tag = MifareTag::new_1k(KeyATag(mock_tag())).unwrap();
+        match tag.dump(KeyOption::KeyB, &Key::DEFAULT, Some(0xEE)) {
+            Err(DumpError::Authentication { sector, error: AuthError::KeyUnsupported(KeyOption::KeyB) }) => assert_eq!(u8::from(sector), 0),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(tag.dump(KeyOption::KeyA, &Key::DEFAULT, None).unwrap().len(), 1024);
+    }
+}
@@ -0,0 +1,301 @@
+use core::fmt;
+use std::fmt::Write as FmtWrite;
+use std::path::Path;
+use std::string::String;
+use std::vec::Vec;
+use crate::numerics::{TagCapacity, SectorNumber, SectorBlockOffset};
+
+/// Error returned when reading or writing invalid image file.
+#[derive(Debug)]
+pub enum FormatError {
+    /// Reading or writing of the file failed.
+    Io(::std::io::Error),
+    /// Image has `len` bytes, but the tag has `expected` bytes.
+    InvalidImageSize { len: usize, expected: usize },
+    /// `.eml` file has `lines` lines, but the tag has `expected` blocks.
+    InvalidLineCount { lines: usize, expected: usize },
+    /// Line (numbered from 1) of `.eml` file isn't 32 hex digits.
+    InvalidLine { line: usize },
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FormatError::Io(ref error) => write!(f, "failed to access image file: {}", error),
+            FormatError::InvalidImageSize { len, expected } => write!(f, "image has {} bytes instead of {}", len, expected),
+            FormatError::InvalidLineCount { lines, expected } => write!(f, "image has {} lines instead of {}", lines, expected),
+            FormatError::InvalidLine { line } => write!(f, "invalid line {} of image", line),
+        }
+    }
+}
+
+impl ::std::error::Error for FormatError {
+    fn source(&self) -> Option<&(dyn ::std::error::Error + 'static)> {
+        match *self {
+            FormatError::Io(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+fn check_size<Cap: TagCapacity>(image: &[u8]) -> Result<(), FormatError> {
+    let expected = Cap::bytes() as usize;
+    if image.len() != expected {
+        return Err(FormatError::InvalidImageSize { len: image.len(), expected: expected });
+    }
+    Ok(())
+}
+
+/// Formats image of the tag (as produced by `MifareTag::dump()`) in `.eml` format used by
+/// Proxmark3 - one block per line as 32 lowercase hex digits.
+pub fn to_eml<Cap: TagCapacity>(image: &[u8]) -> Result<String, FormatError> {
+    check_size::<Cap>(image)?;
+
+    let mut eml = String::with_capacity(image.len() * 2 + image.len() / 16);
+    for block in image.chunks(16) {
+        for byte in block {
+            // Writing to String can't fail
+            write!(eml, "{:02x}", byte).expect("failed to format block");
+        }
+        eml.push('\n');
+    }
+    Ok(eml)
+}
+
+/// Parses image in `.eml` format.
+///
+/// The file must contain exactly one line per block (e.g. 64 for 1K tags, 256 for 4K tags).
+/// Case of hex digits doesn't matter, whitespace around them and empty lines at the end are
+/// ignored.
+pub fn parse_eml<Cap: TagCapacity>(eml: &str) -> Result<Vec<u8>, FormatError> {
+    let lines: Vec<&str> = eml.trim_end().lines().map(str::trim).collect();
+    let expected = Cap::max_blocks() as usize;
+    if lines.len() != expected {
+        return Err(FormatError::InvalidLineCount { lines: lines.len(), expected: expected });
+    }
+
+    let mut image = Vec::with_capacity(Cap::bytes() as usize);
+    for (i, line) in lines.iter().enumerate() {
+        // from_str_radix() alone would accept e.g. "+f"
+        if line.len() != 32 || !line.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return Err(FormatError::InvalidLine { line: i + 1 });
+        }
+        for j in 0..16 {
+            // Always valid, all characters are hex digits
+            let byte = u8::from_str_radix(&line[(j * 2)..(j * 2 + 2)], 16).expect("invalid hex digit");
+            image.push(byte);
+        }
+    }
+    Ok(image)
+}
+
+/// Formats image of the tag for humans, similarly to Proxmark3 and `nfc-mfclassic`.
+///
+/// Each sector starts with a header line and is followed by an empty line. Each block is
+/// printed on a single line as `block | hex bytes | ASCII`, where non-printable characters
+/// are replaced with dots, and sector trailers are marked with `(trailer)`.
+pub fn hexdump<Cap: TagCapacity>(image: &[u8]) -> Result<String, FormatError> {
+    check_size::<Cap>(image)?;
+
+    let mut dump = String::new();
+    // Writing to String can't fail
+    for sector in SectorNumber::<Cap>::all() {
+        let sector_offset = SectorBlockOffset::from(sector);
+        let first_block = u8::from(sector_offset) as usize;
+        let trailer = u8::from(sector_offset.sector_trailer()) as usize;
+
+        writeln!(dump, "sector {}", u8::from(sector)).expect("failed to format sector");
+        for block in first_block..=trailer {
+            let data = &image[(block * 16)..(block * 16 + 16)];
+            write!(dump, "{:3} |", block).expect("failed to format block");
+            for byte in data {
+                write!(dump, " {:02x}", byte).expect("failed to format block");
+            }
+            dump.push_str(" | ");
+            for byte in data {
+                dump.push(if *byte >= 0x20 && *byte < 0x7F { *byte as char } else { '.' });
+            }
+            if block == trailer {
+                dump.push_str(" (trailer)");
+            }
+            dump.push('\n');
+        }
+        dump.push('\n');
+    }
+    Ok(dump)
+}
+
+/// Reads and parses `.eml` file. See `parse_eml()`.
+pub fn load_eml<Cap: TagCapacity, P: AsRef<Path>>(path: P) -> Result<Vec<u8>, FormatError> {
+    let eml = ::std::fs::read_to_string(path).map_err(FormatError::Io)?;
+    parse_eml::<Cap>(&eml)
+}
+
+/// Writes image of the tag into `.eml` file. See `to_eml()`.
+pub fn save_eml<Cap: TagCapacity, P: AsRef<Path>>(path: P, image: &[u8]) -> Result<(), FormatError> {
+    let eml = to_eml::<Cap>(image)?;
+    ::std::fs::write(path, eml).map_err(FormatError::Io)
+}
+
+/// Reads image in binary format (`.mfd` or `.dump`) used by `nfc-mfclassic` and `mfoc`,
+/// which is all blocks in order of their addresses, same as `MifareTag::dump()` returns.
+///
+/// Fails with `InvalidImageSize` if the file doesn't have the size of the tag.
+pub fn load_bin<Cap: TagCapacity, P: AsRef<Path>>(path: P) -> Result<Vec<u8>, FormatError> {
+    let image = ::std::fs::read(path).map_err(FormatError::Io)?;
+    check_size::<Cap>(&image)?;
+    Ok(image)
+}
+
+/// Writes image of the tag into file in binary format. See `load_bin()`.
+pub fn save_bin<Cap: TagCapacity, P: AsRef<Path>>(path: P, image: &[u8]) -> Result<(), FormatError> {
+    check_size::<Cap>(image)?;
+    ::std::fs::write(path, image).map_err(FormatError::Io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::numerics::{Cap1K, Cap4K};
+
+    // Image with each byte set to the lower byte of its position
+    fn image(len: usize) -> Vec<u8> {
+        (0..len).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn eml_round_trip() {
+        let image_1k = image(1024);
+        let eml = to_eml::<Cap1K>(&image_1k).unwrap();
+        assert_eq!(eml.lines().count(), 64);
+        assert_eq!(eml.lines().nth(1), Some("101112131415161718191a1b1c1d1e1f"));
+        assert_eq!(parse_eml::<Cap1K>(&eml).unwrap(), image_1k);
+
+        let image_4k = image(4096);
+        let eml = to_eml::<Cap4K>(&image_4k).unwrap();
+        assert_eq!(eml.lines().count(), 256);
+        assert_eq!(parse_eml::<Cap4K>(&eml).unwrap(), image_4k);
+    }
+
+    #[test]
+    fn eml_tolerates_whitespace() {
+        let image = image(1024);
+        let eml = to_eml::<Cap1K>(&image).unwrap().to_uppercase().replace('\n', " \t\r\n") + "\n\n  \n";
+        assert_eq!(parse_eml::<Cap1K>(&eml).unwrap(), image);
+    }
+
+    #[test]
+    fn eml_with_wrong_line_count() {
+        let eml = to_eml::<Cap1K>(&image(1024)).unwrap();
+        match parse_eml::<Cap4K>(&eml) {
+            Err(FormatError::InvalidLineCount { lines: 64, expected: 256 }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        let truncated: String = eml.lines().skip(1).map(|line| format!("{}\n", line)).collect();
+        match parse_eml::<Cap1K>(&truncated) {
+            Err(FormatError::InvalidLineCount { lines: 63, expected: 64 }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        // Empty lines are allowed only at the end
+        let gap = eml.replacen('\n', "\n\n", 1);
+        match parse_eml::<Cap1K>(&gap) {
+            Err(FormatError::InvalidLineCount { lines: 65, expected: 64 }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn eml_with_invalid_line() {
+        let eml = to_eml::<Cap1K>(&image(1024)).unwrap();
+        for invalid in &["+f0102030405060708090a0b0c0d0e0f", "0g0102030405060708090a0b0c0d0e0f", "000102030405060708090a0b0c0d0e", "00 01 02 03 04 05 06 07 08 09 0a"] {
+            let eml = eml.replacen("101112131415161718191a1b1c1d1e1f", invalid, 1);
+            match parse_eml::<Cap1K>(&eml) {
+                Err(FormatError::InvalidLine { line: 2 }) => (),
+                result => panic!("unexpected result of {:?}: {:?}", invalid, result),
+            }
+        }
+    }
+
+    #[test]
+    fn files_round_trip() {
+        let dir = ::std::env::temp_dir();
+        let bin_path = dir.join(format!("mifare-format-test-{}.mfd", ::std::process::id()));
+        let eml_path = dir.join(format!("mifare-format-test-{}.eml", ::std::process::id()));
+        let image = image(1024);
+
+        save_bin::<Cap1K, _>(&bin_path, &image).unwrap();
+        assert_eq!(::std::fs::read(&bin_path).unwrap(), image);
+        assert_eq!(load_bin::<Cap1K, _>(&bin_path).unwrap(), image);
+        match load_bin::<Cap4K, _>(&bin_path) {
+            Err(FormatError::InvalidImageSize { len: 1024, expected: 4096 }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        save_eml::<Cap1K, _>(&eml_path, &image).unwrap();
+        assert_eq!(load_eml::<Cap1K, _>(&eml_path).unwrap(), image);
+
+        ::std::fs::remove_file(&bin_path).unwrap();
+        ::std::fs::remove_file(&eml_path).unwrap();
+        match save_bin::<Cap1K, _>(&bin_path, &image[..1008]) {
+            Err(FormatError::InvalidImageSize { len: 1008, expected: 1024 }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(!bin_path.exists());
+    }
+
+    #[test]
+    fn hexdump_of_4k_image() {
+        let mut image = vec![0; 4096];
+        let trailer = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x07, 0x80, 0x69, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+        image[(127 * 16)..(128 * 16)].copy_from_slice(&trailer);
+        image[(143 * 16)..(144 * 16)].copy_from_slice(&trailer);
+        image[(126 * 16)..(127 * 16)].copy_from_slice(b"Hello, world!\x00\x01\x7f");
+        image[(128 * 16)..(128 * 16 + 4)].copy_from_slice(b"4K~ ");
+        let dump = hexdump::<Cap4K>(&image).unwrap();
+
+        // Last small sector and the first large one
+        let expected = concat!(
+            "sector 31\n",
+            "124 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "125 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "126 | 48 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 00 01 7f | Hello, world!...\n",
+            "127 | ff ff ff ff ff ff ff 07 80 69 ff ff ff ff ff ff | .........i...... (trailer)\n",
+            "\n",
+            "sector 32\n",
+            "128 | 34 4b 7e 20 00 00 00 00 00 00 00 00 00 00 00 00 | 4K~ ............\n",
+            "129 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "130 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "131 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "132 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "133 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "134 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "135 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "136 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "137 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "138 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "139 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "140 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "141 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "142 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................\n",
+            "143 | ff ff ff ff ff ff ff 07 80 69 ff ff ff ff ff ff | .........i...... (trailer)\n",
+            "\n",
+            "sector 33\n",
+        );
+        let start = dump.find("sector 31\n").unwrap();
+        assert_eq!(&dump[start..(start + expected.len())], expected);
+
+        assert!(dump.starts_with("sector 0\n  0 | 00 "));
+        assert!(dump.ends_with("255 | 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 | ................ (trailer)\n\n"));
+        // Header, blocks and empty line of each sector
+        assert_eq!(dump.lines().count(), 40 + 256 + 40);
+        assert_eq!(dump.matches("(trailer)").count(), 40);
+    }
+
+    #[test]
+    fn hexdump_checks_size() {
+        match hexdump::<Cap4K>(&image(1024)) {
+            Err(FormatError::InvalidImageSize { len: 1024, expected: 4096 }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use core::fmt;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+use crate::KeyOption;
+use crate::numerics::SectorNumber4K;
+
+/// Error returned when parsing invalid key.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum KeyParseError {
+    /// The string has `len` bytes instead of 12.
+    InvalidLength(usize),
+    /// The string contains character which isn't hex digit.
+    InvalidCharacter(char),
+}
+
+impl fmt::Display for KeyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            KeyParseError::InvalidLength(len) => write!(f, "key has {} characters instead of 12", len),
+            KeyParseError::InvalidCharacter(c) => write!(f, "invalid hex digit {:?} in key", c),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for KeyParseError {}
+
+/// Key used for authentication to sector (either key A or key B).
+///
+/// Formatting (both `Display` and `Debug`) prints it as 12 hex digits.
+#[derive(Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Key([u8; 6]);
+
+impl Key {
+    /// Key of blank tags, FFFFFFFFFFFF.
+    pub const DEFAULT: Key = Key([0xFF; 6]);
+
+    /// Creates Key from bytes.
+    pub const fn new(bytes: [u8; 6]) -> Self {
+        Key(bytes)
+    }
+
+    /// Parses key from exactly 12 hex digits (e.g. "A0A1A2A3A4A5"). Case doesn't matter.
+    pub fn from_hex(hex: &str) -> Result<Self, KeyParseError> {
+        if hex.len() != 12 {
+            return Err(KeyParseError::InvalidLength(hex.len()));
+        }
+
+        let mut key = [0; 6];
+        for (i, c) in hex.chars().enumerate() {
+            let digit = c.to_digit(16).ok_or(KeyParseError::InvalidCharacter(c))? as u8;
+            key[i / 2] |= digit << (4 * (1 - i % 2));
+        }
+        Ok(Key(key))
+    }
+
+    /// Returns bytes of the key.
+    pub fn as_bytes(&self) -> &[u8; 6] {
+        &self.0
+    }
+}
+
+impl FromStr for Key {
+    type Err = KeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Key::from_hex(s)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Key({})", self)
+    }
+}
+
+impl From<[u8; 6]> for Key {
+    fn from(bytes: [u8; 6]) -> Self {
+        Key(bytes)
+    }
+}
+
+impl<'a> From<&'a [u8; 6]> for Key {
+    fn from(bytes: &'a [u8; 6]) -> Self {
+        Key(*bytes)
+    }
+}
+
+impl<'a> From<&'a Key> for Key {
+    fn from(key: &'a Key) -> Self {
+        *key
+    }
+}
+
+impl From<Key> for [u8; 6] {
+    fn from(key: Key) -> Self {
+        key.0
+    }
+}
+
+/// Supplies keys for authentication to sectors.
+pub trait KeyProvider {
+    /// Returns key of `sector` selected by `option` or `None` if it's not known.
+    fn key(&self, sector: SectorNumber4K, option: KeyOption) -> Option<Key>;
+}
+
+/// Single key used for all sectors (as both key A and key B).
+impl KeyProvider for Key {
+    fn key(&self, _sector: SectorNumber4K, _option: KeyOption) -> Option<Key> {
+        Some(*self)
+    }
+}
+
+/// Error returned when loading invalid key file.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum KeyFileError {
+    /// Reading of the file failed.
+    Io(::std::io::Error),
+    /// Line (numbered from 1) doesn't have the form `<sector> <A|B> <key>` or the sector
+    /// number is invalid.
+    InvalidLine { line: usize },
+    /// Key on the line (numbered from 1) is invalid.
+    InvalidKey { line: usize, error: KeyParseError },
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for KeyFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            KeyFileError::Io(ref error) => write!(f, "failed to read key file: {}", error),
+            KeyFileError::InvalidLine { line } => write!(f, "invalid line {} of key file", line),
+            KeyFileError::InvalidKey { line, ref error } => write!(f, "invalid key on line {} of key file: {}", line, error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for KeyFileError {
+    fn source(&self) -> Option<&(dyn ::std::error::Error + 'static)> {
+        match *self {
+            KeyFileError::Io(ref error) => Some(error),
+            KeyFileError::InvalidKey { ref error, .. } => Some(error),
+            KeyFileError::InvalidLine { .. } => None,
+        }
+    }
+}
+
+/// Keys of individual sectors, e.g. loaded from a key file.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct KeyMap {
+    keys: HashMap<(u8, KeyOption), Key>,
+}
+
+#[cfg(feature = "std")]
+impl KeyMap {
+    /// Creates empty KeyMap.
+    pub fn new() -> Self {
+        KeyMap { keys: HashMap::new() }
+    }
+
+    /// Sets key of sector, returning the previous one.
+    pub fn insert(&mut self, sector: SectorNumber4K, option: KeyOption, key: Key) -> Option<Key> {
+        self.keys.insert((sector.into(), option), key)
+    }
+
+    /// Parses key file.
+    ///
+    /// Each line has the form `<sector> <A|B> <key>`, e.g. `1 A A0A1A2A3A4A5`. Empty lines and
+    /// lines starting with `#` are ignored. If the same key is specified multiple times, the
+    /// last one is used.
+    pub fn parse(key_file: &str) -> Result<Self, KeyFileError> {
+        let mut key_map = KeyMap::new();
+        for (i, line) in key_file.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid_line = KeyFileError::InvalidLine { line: line_number };
+            let mut fields = line.split_whitespace();
+            let (sector, option, key) = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(sector), Some(option), Some(key), None) => (sector, option, key),
+                _ => return Err(invalid_line),
+            };
+            let sector = match sector.parse().ok().and_then(SectorNumber4K::new) {
+                Some(sector) => sector,
+                None => return Err(invalid_line),
+            };
+            let option = match option {
+                "A" | "a" => KeyOption::KeyA,
+                "B" | "b" => KeyOption::KeyB,
+                _ => return Err(invalid_line),
+            };
+            let key = Key::from_hex(key).map_err(|error| KeyFileError::InvalidKey { line: line_number, error: error })?;
+
+            key_map.insert(sector, option, key);
+        }
+        Ok(key_map)
+    }
+
+    /// Reads and parses key file. See `parse()` for its format.
+    pub fn load<P: AsRef<::std::path::Path>>(path: P) -> Result<Self, KeyFileError> {
+        let key_file = ::std::fs::read_to_string(path).map_err(KeyFileError::Io)?;
+        KeyMap::parse(&key_file)
+    }
+}
+
+#[cfg(feature = "std")]
+impl KeyProvider for KeyMap {
+    fn key(&self, sector: SectorNumber4K, option: KeyOption) -> Option<Key> {
+        self.keys.get(&(sector.into(), option)).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_hex() {
+        let key = Key::new([0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5]);
+        assert_eq!(Key::from_hex("A0A1A2A3A4A5"), Ok(key));
+        assert_eq!(Key::from_hex("a0a1a2A3A4A5"), Ok(key));
+        assert_eq!("A0A1A2A3A4A5".parse::<Key>(), Ok(key));
+        assert_eq!(Key::from_hex("FFFFFFFFFFFF"), Ok(Key::DEFAULT));
+    }
+
+    #[test]
+    fn from_hex_with_invalid_length() {
+        assert_eq!(Key::from_hex("A0A1A2A3A4A"), Err(KeyParseError::InvalidLength(11)));
+        assert_eq!(Key::from_hex("A0A1A2A3A4A5A"), Err(KeyParseError::InvalidLength(13)));
+        assert_eq!(Key::from_hex(""), Err(KeyParseError::InvalidLength(0)));
+        assert_eq!("A0A1A2A3A4A".parse::<Key>(), Err(KeyParseError::InvalidLength(11)));
+    }
+
+    #[test]
+    fn from_hex_with_invalid_character() {
+        assert_eq!(Key::from_hex("A0A1A2A3A4AG"), Err(KeyParseError::InvalidCharacter('G')));
+        assert_eq!(Key::from_hex("+0A1A2A3A4A5"), Err(KeyParseError::InvalidCharacter('+')));
+        assert_eq!(Key::from_hex("A0A1A2 3A4A5"), Err(KeyParseError::InvalidCharacter(' ')));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn formatting() {
+        let key = Key::new([0xA0, 0xA1, 0xA2, 0xB3, 0x0C, 0xFF]);
+        assert_eq!(std::format!("{}", key), "A0A1A2B30CFF");
+        assert_eq!(std::format!("{:?}", key), "Key(A0A1A2B30CFF)");
+        assert_eq!(std::format!("{}", key).parse::<Key>(), Ok(key));
+    }
+
+    #[cfg(feature = "std")]
+    fn sector(number: u8) -> SectorNumber4K {
+        SectorNumber4K::new(number).unwrap()
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn parse_key_map() {
+        let key_file = "# keys of the tag\n\n0 A A0A1A2A3A4A5\n  0 b B0B1B2B3B4B5  \n39 a ffffffffffff\n# 1 A 000000000000\n39 A 0123456789AB\n";
+        let key_map = KeyMap::parse(key_file).unwrap();
+        assert_eq!(key_map.key(sector(0), KeyOption::KeyA), Some(Key::new([0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5])));
+        assert_eq!(key_map.key(sector(0), KeyOption::KeyB), Some(Key::new([0xB0, 0xB1, 0xB2, 0xB3, 0xB4, 0xB5])));
+        // Last key wins
+        assert_eq!(key_map.key(sector(39), KeyOption::KeyA), Some(Key::new([0x01, 0x23, 0x45, 0x67, 0x89, 0xAB])));
+        assert_eq!(key_map.key(sector(39), KeyOption::KeyB), None);
+        // Commented out
+        assert_eq!(key_map.key(sector(1), KeyOption::KeyA), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn parse_key_map_with_invalid_line() {
+        for &line in &["40 A FFFFFFFFFFFF", "-1 A FFFFFFFFFFFF", "x A FFFFFFFFFFFF", "1 C FFFFFFFFFFFF", "1 AB FFFFFFFFFFFF", "1 A", "1 A FFFFFFFFFFFF FF"] {
+            let key_file = std::format!("0 A FFFFFFFFFFFF\n\n{}\n", line);
+            match KeyMap::parse(&key_file) {
+                Err(KeyFileError::InvalidLine { line: 3 }) => (),
+                result => panic!("unexpected result for {:?}: {:?}", line, result),
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn parse_key_map_with_invalid_key() {
+        match KeyMap::parse("# comment\n1 A FFFFFFFFFFF\n") {
+            Err(KeyFileError::InvalidKey { line: 2, error: KeyParseError::InvalidLength(11) }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match KeyMap::parse("1 B FFFFFFFFFFFX\n") {
+            Err(KeyFileError::InvalidKey { line: 1, error: KeyParseError::InvalidCharacter('X') }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
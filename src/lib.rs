@@ -1,135 +1,1487 @@
+#![no_std]
+
+#[cfg(feature = "std")]
+#[macro_use]
+extern crate std;
+#[macro_use]
+extern crate log;
 #[cfg(feature = "with_pn532")]
 extern crate pn532;
 #[cfg(feature = "with_pn532")]
 mod pn532_impl;
+#[cfg(feature = "diversify")]
+extern crate aes;
+#[cfg(feature = "with_mfrc522")]
+extern crate mfrc522;
 
 /// Typesafe numeric types related to Mifare tags.
 pub mod numerics;
 
-pub use numerics::{SectorNumber1K, SectorNumber4K, BlockOffset};
+/// Handling of tag UIDs.
+pub mod uid;
+
+/// Encoding and decoding of value blocks.
+pub mod value;
+
+/// Access conditions stored in sector trailers.
+pub mod access;
+
+/// Software implementation of Crypto1 cipher.
+pub mod crypto1;
+
+/// Authentication keys.
+pub mod key;
+
+/// Retrying of failed communication.
+pub mod retry;
+
+/// Dumping and restoring whole tag and other operations spanning multiple sectors.
+pub mod dump;
+
+/// Support for Mifare Ultralight tags.
+#[cfg(feature = "ultralight")]
+pub mod ultralight;
+
+/// Reading and writing of tag images in formats used by other tools.
+#[cfg(feature = "std")]
+pub mod format;
+
+/// Mifare Application Directory.
+pub mod mad;
+
+/// Reading of NDEF messages.
+#[cfg(feature = "std")]
+pub mod ndef;
+
+/// Support for magic (backdoored) tags.
+pub mod magic;
+
+/// Implementation of `NFCTag` for MFRC522 readers.
+#[cfg(feature = "with_mfrc522")]
+pub mod mfrc522_impl;
 
-// Abbreviation
-type SectorBlockOffset4K = numerics::SectorBlockOffset<numerics::Cap4K>;
-type AbsoluteBlockOffset4K = numerics::AbsoluteBlockOffset<numerics::Cap4K>;
+/// Key diversification (NXP AN10922, Classic keys are derived in crate-specific way).
+#[cfg(feature = "diversify")]
+pub mod diversify;
+
+/// Support for readers with async drivers.
+#[cfg(feature = "async")]
+pub mod async_tag;
+
+/// Fake tag for testing without hardware.
+#[cfg(feature = "mock")]
+pub mod mock;
+
+mod command;
+
+pub use numerics::{NumericError, SectorNumberMini, SectorNumber1K, SectorNumber2K, SectorNumber4K, BlockOffset, Sectors, CapMini, Cap1K, Cap2K, Cap4K};
+pub use uid::Uid;
+pub use key::{Key, KeyParseError, KeyProvider};
+#[cfg(feature = "std")]
+pub use key::{KeyMap, KeyFileError};
+pub use value::{ValueBlock, ValueBlockError};
+pub use access::{AccessBits, AccessCondition, SectorTrailer};
+pub use retry::{RetryPolicy, RetryTag};
+pub use magic::Gen1aUnlocked;
+pub use mad::{Mad, MadError};
+#[cfg(feature = "std")]
+pub use ndef::NdefError;
+#[cfg(feature = "with_mfrc522")]
+pub use mfrc522_impl::{Mfrc522Tag, Mfrc522Error};
+#[cfg(feature = "diversify")]
+pub use diversify::diversify_key;
+#[cfg(feature = "std")]
+pub use format::FormatError;
+pub use dump::{DumpError, RestoreOptions, TrailerHandling};
+#[cfg(feature = "async")]
+pub use async_tag::{AsyncNFCTag, AsyncMifareTag, AsyncAuthenticatedSector};
+
+use numerics::{TagCapacity, SectorBlockOffset, AbsoluteBlockOffset};
+use access::AccessBitsError;
+use core::marker::PhantomData;
+use core::fmt;
+
+/// Bound on `NFCTag::TransceiveError`.
+///
+/// It's `std::error::Error` if the `std` feature is enabled, `core::fmt::Debug` otherwise.
+#[cfg(feature = "std")]
+pub trait TagError: ::std::error::Error {}
+
+#[cfg(feature = "std")]
+impl<E: ::std::error::Error> TagError for E {}
+
+/// Bound on `NFCTag::TransceiveError`.
+///
+/// It's `std::error::Error` if the `std` feature is enabled, `core::fmt::Debug` otherwise.
+#[cfg(not(feature = "std"))]
+pub trait TagError: ::core::fmt::Debug {}
+
+#[cfg(not(feature = "std"))]
+impl<E: ::core::fmt::Debug> TagError for E {}
 
 /// Represents NFC tag which could be Mifare tag.
+///
+/// This is the only thing a reader has to implement, all Mifare logic is built on top of
+/// it. Usually it's enough to implement `tag_id()` and `transceive()` (see `pn532_impl.rs`
+/// or `mfrc522_impl.rs`); the other methods provide optional capabilities.
 pub trait NFCTag {
     /// Error type of transceive() method.
-    type TransceiveError: ::std::error::Error;
+    type TransceiveError: TagError;
 
     /// ID of tag. Must be 4 or 7 for valid Mifare tag.
     fn tag_id(&self) -> &[u8];
 
+    /// ATQA (SENS_RES) returned by the tag during anticollision.
+    ///
+    /// Returns `None` if the backend doesn't provide it, which is what the default
+    /// implementation does.
+    fn atqa(&self) -> Option<u16> {
+        None
+    }
+
+    /// SAK (SEL_RES) returned by the tag during anticollision.
+    ///
+    /// Returns `None` if the backend doesn't provide it, which is what the default
+    /// implementation does.
+    fn sak(&self) -> Option<u8> {
+        None
+    }
+
+    /// BCC bytes received during anticollision, one for each cascade level.
+    ///
+    /// Returns `None` if the backend doesn't provide them, which is what the default
+    /// implementation does. See `Uid::verify_bcc()`.
+    fn bcc(&self) -> Option<&[u8]> {
+        None
+    }
+
     /// This function will be used for communication with the tag.
+    ///
+    /// `data_to_tag` is Mifare command without CRC, which the reader appends (and checks
+    /// in response, which is returned without it). The reader is expected to handle
+    /// Crypto1, so that the commands are:
+    ///
+    /// * `60|61 block key[6] uid[4|7]` - authentication with key A or B; empty response
+    ///   means success
+    /// * `30 block` - read, the response has 16 bytes
+    /// * `A0 block data[16]` - write, the reader sends data after the tag acknowledges
+    ///   the command
+    /// * `C0|C1|C2 block operand[4]` - decrement, increment or restore
+    /// * `B0 block` - transfer
+    /// * `50 00` - halt, the tag doesn't answer it
+    ///
+    /// Tag answers write, value and transfer commands with 4 bit ACK (0xA) or NAK. The
+    /// reader may return ACK as empty response, NAK has to be returned as single byte
+    /// containing the code in its lower nibble, so that it can be reported as
+    /// `Error::NakReceived`.
+    ///
+    /// This matches what PN532 does on its own. Readers which don't know these commands
+    /// (e.g. MFRC522) have to map them to their operations.
     fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Self::TransceiveError>;
+
+    /// Sends frame of which only `last_byte_bits` (1 - 8) bits of the last byte are sent,
+    /// without CRC, and receives response.
+    ///
+    /// This is needed for non-standard commands, like unlocking of magic tags. Returns `None`
+    /// if the backend can't send such frames, which is what the default implementation does.
+    fn transceive_bits(&mut self, _data_to_tag: &[u8], _last_byte_bits: u8, _data_from_tag: &mut [u8]) -> Option<Result<usize, Self::TransceiveError>> {
+        None
+    }
+
+    /// Checks whether the tag is still in the field without disturbing its state.
+    ///
+    /// Returns `None` if the backend can't perform such check, which is what the default
+    /// implementation does. Of the backends in this crate, only `MockTag` supports it.
+    fn check_presence(&mut self) -> Option<bool> {
+        None
+    }
+
+    /// Checks whether the backend can authenticate using `key_option`.
+    ///
+    /// Returns `true` by default. `Mfrc522Tag` returns `false` for key B, which the reader
+    /// driver can't use. `MifareTag` refuses unsupported authentication with
+    /// `AuthError::KeyUnsupported` without sending anything.
+    fn supports_key(&self, _key_option: KeyOption) -> bool {
+        true
+    }
+
+    /// Checks whether `error` returned by `transceive()` means that the tag didn't respond.
+    ///
+    /// Used by `MifareTag::halt()`, because the tag accepts HALT by not responding. Returns
+    /// `None` if the backend can't tell timeout from other errors, which is what the default
+    /// implementation does. Of the backends in this crate, only `MockTag` supports it;
+    /// `Mfrc522Tag` doesn't need it, because the reader reports successful HALT itself.
+    fn is_timeout(&self, _error: &Self::TransceiveError) -> Option<bool> {
+        None
+    }
+
+    /// Selects the tag again, so it can be authenticated after failed authentication.
+    ///
+    /// Tags stop responding after failed authentication until they're selected again.
+    /// Returns `None` if the backend can't select the tag again, which is what the default
+    /// implementation does. Backends that select the tag automatically should return
+    /// `Some(Ok(()))`. Without it, `MifareTag::find_key()` and `MifareTag::dump()` with
+    /// `fill_unreadable` fail with `Error::ReselectUnsupported` after the first failed
+    /// authentication.
+    ///
+    /// Implementations must fail if the selected tag isn't the one they were created for,
+    /// otherwise further commands would be sent to another tag.
+    ///
+    /// Of the backends in this crate, `Mfrc522Tag` selects the tag using WUPA and
+    /// anticollision and `MockTag` just checks that it's present. PN532 backend can't select
+    /// the tag again - the tag has to be listed by the reader again, which creates new `Tag`.
+    fn select(&mut self) -> Option<Result<(), Self::TransceiveError>> {
+        None
+    }
 }
 
-/// Type used for selecting authentication key.
+/// Well-known keys commonly used by tags (same as the default keys of `mfoc`).
+pub const DEFAULT_KEYS: [Key; 8] = [
+    Key::new([0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]),
+    Key::new([0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5]),
+    Key::new([0xD3, 0xF7, 0xD3, 0xF7, 0xD3, 0xF7]),
+    Key::new([0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    Key::new([0xB0, 0xB1, 0xB2, 0xB3, 0xB4, 0xB5]),
+    Key::new([0x4D, 0x3A, 0x99, 0xC3, 0x51, 0xDD]),
+    Key::new([0x1A, 0x98, 0x2C, 0x7E, 0x45, 0x9A]),
+    Key::new([0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]),
+];
+
+/// Type of Mifare Classic tag.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ClassicType {
+    Mini,
+    Classic1K,
+    Classic2K,
+    Classic4K,
+}
+
+impl ClassicType {
+    /// Determines type of tag from its SAK. Returns `None` if it's not Mifare Classic.
+    pub fn from_sak(sak: u8) -> Option<Self> {
+        match sak {
+            0x09 => Some(ClassicType::Mini),
+            0x08 | 0x28 | 0x88 => Some(ClassicType::Classic1K),
+            0x19 => Some(ClassicType::Classic2K),
+            0x18 | 0x38 => Some(ClassicType::Classic4K),
+            _ => None,
+        }
+    }
+}
+
+/// Type used for selecting authentication key.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum KeyOption {
     KeyA,
     KeyB,
 }
 
+/// Maximum length of ISO14443-A frame. Used as default limit of response length.
+pub const DEFAULT_MAX_RESPONSE_LEN: usize = 262;
+
+/// Error returned by operations on Mifare tag.
+#[derive(Debug)]
+pub enum Error<E> {
+    /// Communication with the tag failed.
+    Transceive(E),
+    /// Reader reported response longer than the configured maximum.
+    ResponseTooLong { len: usize, max: usize },
+    /// Writing sector trailer would make it permanently unwritable with current key.
+    WouldLockOut,
+    /// The key isn't known and can't be read from the tag.
+    UnknownKey(KeyOption),
+    /// Tag responded with `len` bytes, which the command doesn't expect.
+    UnexpectedResponse { len: usize },
+    /// Tag responded to read with `len` bytes instead of `expected` (e.g. with NAK).
+    UnexpectedLength { len: usize, expected: usize },
+    /// Supplied buffer has `len` bytes, but `required` bytes are needed.
+    BufferTooSmall { len: usize, required: usize },
+    /// Page (of Ultralight tag) is out of range or the range starting at it doesn't fit.
+    PageOutOfRange(u8),
+    /// Block offset points to the trailer of the authenticated sector or past it.
+    BlockOutOfRange(BlockOffset),
+    /// Absolute block offset points to the trailer of the authenticated sector or to
+    /// another sector.
+    AbsoluteBlockOutOfRange(u8),
+    /// Access bits read from sector trailer are corrupted.
+    InvalidAccessBits(AccessBitsError),
+    /// Access conditions don't allow the operation on block at the offset with the key used
+    /// for authentication. Only returned if access checks are enabled, see
+    /// `AuthenticatedSector::set_access_bits()`.
+    AccessDenied(BlockOffset),
+    /// The backend can't send bit frames, see `NFCTag::transceive_bits()`.
+    BitFramesUnsupported,
+    /// The tag has to be selected again, but the backend can't do it, see `NFCTag::select()`.
+    ReselectUnsupported,
+    /// Tag refused write, value or transfer command with 4 bit NAK code.
+    ///
+    /// Codes 0x0 and 0x4 mean invalid operation - the sector isn't authenticated, access
+    /// conditions don't allow the operation or the block doesn't exist. Codes 0x1 and 0x5
+    /// mean parity or CRC error, which is a transmission error and retrying may help.
+    NakReceived(u8),
+}
+
+impl<E: fmt::Display> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Transceive(ref error) => write!(f, "communication with tag failed: {}", error),
+            Error::ResponseTooLong { len, max } => write!(f, "response has {} bytes, maximum is {}", len, max),
+            Error::WouldLockOut => write!(f, "sector trailer would be permanently unwritable with current key"),
+            Error::UnknownKey(key_option) => write!(f, "{:?} isn't known and can't be read from the tag", key_option),
+            Error::UnexpectedResponse { len } => write!(f, "unexpected response of {} bytes", len),
+            Error::UnexpectedLength { len, expected } => write!(f, "tag responded with {} bytes instead of {}", len, expected),
+            Error::BufferTooSmall { len, required } => write!(f, "buffer has {} bytes, but {} bytes are required", len, required),
+            Error::PageOutOfRange(page) => write!(f, "pages starting at {} are out of range", page),
+            Error::BlockOutOfRange(offset) => write!(f, "block offset {} isn't data block of the sector", u8::from(offset)),
+            Error::AbsoluteBlockOutOfRange(block) => write!(f, "block {} isn't data block of the authenticated sector", block),
+            Error::InvalidAccessBits(ref error) => write!(f, "invalid access bits in sector trailer: {}", error),
+            Error::AccessDenied(offset) => write!(f, "access conditions don't allow the operation on block offset {} with the key used for authentication", u8::from(offset)),
+            Error::BitFramesUnsupported => write!(f, "the reader can't send bit frames"),
+            Error::ReselectUnsupported => write!(f, "the reader can't select the tag again"),
+            Error::NakReceived(code) => write!(f, "tag responded with NAK 0x{:X}", code),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: ::std::error::Error + 'static> ::std::error::Error for Error<E> {
+    fn source(&self) -> Option<&(dyn ::std::error::Error + 'static)> {
+        match *self {
+            Error::Transceive(ref error) => Some(error),
+            Error::InvalidAccessBits(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Error returned when authentication fails.
+#[derive(Debug)]
+pub enum AuthError<E> {
+    /// Communication with the tag failed.
+    ///
+    /// Note that some backends report rejected authentication as communication error.
+    Transceive(Error<E>),
+    /// Tag responded with NAK, which usually means the key is wrong.
+    AuthenticationRejected,
+    /// The backend can't authenticate using this key, see `NFCTag::supports_key()`.
+    KeyUnsupported(KeyOption),
+}
+
+impl<E: fmt::Display> fmt::Display for AuthError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AuthError::Transceive(ref error) => write!(f, "authentication failed: {}", error),
+            AuthError::AuthenticationRejected => write!(f, "authentication rejected, the key is probably wrong"),
+            AuthError::KeyUnsupported(key_option) => write!(f, "the reader can't authenticate using {:?}", key_option),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: ::std::error::Error + 'static> ::std::error::Error for AuthError<E> {
+    fn source(&self) -> Option<&(dyn ::std::error::Error + 'static)> {
+        match *self {
+            AuthError::Transceive(ref error) => Some(error),
+            AuthError::AuthenticationRejected => None,
+            AuthError::KeyUnsupported(_) => None,
+        }
+    }
+}
+
+/// Error returned by `AuthenticatedSector::write_blocks()`.
+#[derive(Debug)]
+pub enum WriteBlocksError<E> {
+    /// Writing of block at `offset` failed. `written` blocks were written before it.
+    Transceive { written: usize, offset: BlockOffset, error: Error<E> },
+    /// More blocks were supplied than fit before the sector trailer. `written` blocks were
+    /// written, the trailer was left untouched.
+    TrailerReached { written: usize },
+    /// The start offset is past the sector trailer, nothing was written.
+    BlockOutOfRange(BlockOffset),
+}
+
+impl<E: fmt::Display> fmt::Display for WriteBlocksError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WriteBlocksError::Transceive { written, offset, ref error } => write!(f, "writing of block offset {} failed after writing {} blocks: {}", u8::from(offset), written, error),
+            WriteBlocksError::TrailerReached { written } => write!(f, "sector trailer reached after writing {} blocks", written),
+            WriteBlocksError::BlockOutOfRange(offset) => write!(f, "block offset {} is past the sector trailer", u8::from(offset)),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: ::std::error::Error + 'static> ::std::error::Error for WriteBlocksError<E> {
+    fn source(&self) -> Option<&(dyn ::std::error::Error + 'static)> {
+        match *self {
+            WriteBlocksError::Transceive { ref error, .. } => Some(error),
+            WriteBlocksError::TrailerReached { .. } => None,
+            WriteBlocksError::BlockOutOfRange(_) => None,
+        }
+    }
+}
+
+/// Error returned by `AuthenticatedSector::read_value()`.
+#[derive(Debug)]
+pub enum ValueReadError<E> {
+    /// Reading of the block failed.
+    Transceive(Error<E>),
+    /// The block isn't valid value block.
+    InvalidValueBlock(ValueBlockError),
+}
+
+impl<E: fmt::Display> fmt::Display for ValueReadError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValueReadError::Transceive(ref error) => write!(f, "reading of value block failed: {}", error),
+            ValueReadError::InvalidValueBlock(ref error) => write!(f, "invalid value block: {}", error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: ::std::error::Error + 'static> ::std::error::Error for ValueReadError<E> {
+    fn source(&self) -> Option<&(dyn ::std::error::Error + 'static)> {
+        match *self {
+            ValueReadError::Transceive(ref error) => Some(error),
+            ValueReadError::InvalidValueBlock(ref error) => Some(error),
+        }
+    }
+}
+
 /// Encapsulates Mifare tag.
-pub struct MifareTag<T> {
+///
+/// The capacity of the tag is tracked in type, so that it's not possible to authenticate
+/// sector which the tag doesn't have.
+pub struct MifareTag<T, Cap = Cap4K> {
     tag: T,
+    max_response_len: usize,
+    dry_run: bool,
+    _phantom: PhantomData<Cap>,
 }
 
 impl<T: NFCTag> MifareTag<T> {
-    /// Checks whether tag_id has correct length and creates MifareTag.
+    /// Checks whether tag_id has correct length and creates MifareTag with 4K capacity.
     pub fn new(tag: T) -> Option<Self> {
+        MifareTag::with_capacity(tag)
+    }
+
+    /// Same as `new()`, but also fails if the backend provides BCC and it doesn't match.
+    ///
+    /// See `verify_bcc()`.
+    pub fn new_checked(tag: T) -> Option<Self> {
+        MifareTag::new(tag).and_then(|tag| if tag.verify_bcc() == Some(false) { None } else { Some(tag) })
+    }
+
+    /// Same as `new()`, but also fails if the backend provides SAK and it isn't SAK of
+    /// Mifare Classic.
+    ///
+    /// See `with_capacity_classic()`.
+    pub fn new_classic(tag: T) -> Option<Self> {
+        MifareTag::with_capacity_classic(tag)
+    }
+
+    /// Same as `new()`, for symmetry with `new_1k()`.
+    pub fn new_4k(tag: T) -> Option<Self> {
+        MifareTag::with_capacity(tag)
+    }
+}
+
+impl<T: NFCTag> MifareTag<T, CapMini> {
+    /// Checks whether tag_id has correct length and creates MifareTag with Mini capacity.
+    pub fn new_mini(tag: T) -> Option<Self> {
+        MifareTag::with_capacity(tag)
+    }
+}
+
+impl<T: NFCTag> MifareTag<T, Cap1K> {
+    /// Checks whether tag_id has correct length and creates MifareTag with 1K capacity.
+    pub fn new_1k(tag: T) -> Option<Self> {
+        MifareTag::with_capacity(tag)
+    }
+}
+
+impl<T: NFCTag> MifareTag<T, Cap2K> {
+    /// Checks whether tag_id has correct length and creates MifareTag with 2K capacity.
+    pub fn new_2k(tag: T) -> Option<Self> {
+        MifareTag::with_capacity(tag)
+    }
+}
+
+impl<T: NFCTag, Cap: TagCapacity> MifareTag<T, Cap> {
+    /// Checks whether tag_id has correct length and creates MifareTag with capacity `Cap`.
+    pub fn with_capacity(tag: T) -> Option<Self> {
         let id_len = tag.tag_id().len();
         if id_len == 4 || id_len == 7 {
-            Some(MifareTag { tag: tag })
+            Some(MifareTag { tag: tag, max_response_len: DEFAULT_MAX_RESPONSE_LEN, dry_run: false, _phantom: PhantomData })
         } else {
             None
         }
     }
 
+    /// Same as `with_capacity()`, but also fails if the backend provides SAK and it isn't SAK
+    /// of Mifare Classic.
+    ///
+    /// Only SAK values known to `ClassicType::from_sak()` are accepted, so this rejects some
+    /// tags that can be used as Mifare Classic, e.g. smart cards emulating it or Mifare Plus
+    /// in security level 2.
+    pub fn with_capacity_classic(tag: T) -> Option<Self> {
+        let is_classic = match tag.sak() {
+            Some(sak) => ClassicType::from_sak(sak).is_some(),
+            None => true,
+        };
+        if is_classic {
+            MifareTag::with_capacity(tag)
+        } else {
+            None
+        }
+    }
+
+    /// Sets maximum length of response accepted from the reader.
+    ///
+    /// Responses are never read past this limit and reporting a longer one results in
+    /// `Error::ResponseTooLong`. Defaults to `DEFAULT_MAX_RESPONSE_LEN`.
+    pub fn set_max_response_len(&mut self, max_response_len: usize) {
+        self.max_response_len = max_response_len;
+    }
+
+    /// Returns maximum length of response accepted from the reader.
+    pub fn max_response_len(&self) -> usize {
+        self.max_response_len
+    }
+
+    /// Enables or disables dry run mode.
+    ///
+    /// In dry run mode, operations that would write to the tag only log (at info level) what
+    /// they would write and return success without sending anything. This includes value
+    /// operations, neither increment, decrement and restore nor transfer is sent. Reading
+    /// works normally, so this can be used to validate provisioning code against a real tag.
+    /// `transceive_raw()` is not affected.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// Returns true if dry run mode is enabled.
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Sends arbitrary data to the tag and receives response.
+    ///
+    /// This is an escape hatch for commands not supported by this crate. Returns length of
+    /// the response, which is limited by `max_response_len()`.
+    pub fn transceive_raw(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Error<T::TransceiveError>> {
+        let max = ::core::cmp::min(data_from_tag.len(), self.max_response_len);
+        let len = self.tag.transceive(data_to_tag, &mut data_from_tag[..max]).map_err(Error::Transceive)?;
+        command::check_response_len(len, max)
+    }
+
     /// Authenticates to sector using key.
-    pub fn authenticate_sector<'s, SN: Into<SectorBlockOffset4K>>(&'s mut self, sector_number: SN, key_option: KeyOption, key: &[u8; 6]) -> Result<AuthenticatedSector<'s, T>, T::TransceiveError> {
+    ///
+    /// Fails with `AuthenticationRejected` if the tag responds with anything else than empty
+    /// response.
+    pub fn authenticate_sector<'s, SN: Into<SectorBlockOffset<Cap>>, K: Into<Key>>(&'s mut self, sector_number: SN, key_option: KeyOption, key: K) -> Result<AuthenticatedSector<'s, T, Cap>, AuthError<T::TransceiveError>> {
         let sector_offset = sector_number.into();
+        let key = key.into();
+        self.send_auth(sector_offset, key_option, &key)?;
+        Ok(AuthenticatedSector { tag: self, sector_offset: sector_offset, key_option: key_option, key: key, access_bits: None })
+    }
 
-        let cmd = match key_option {
-            KeyOption::KeyA => 0x60,
-            KeyOption::KeyB => 0x61,
-        };
+    // Sends authentication command and checks that it was accepted
+    fn send_auth(&mut self, sector_offset: SectorBlockOffset<Cap>, key_option: KeyOption, key: &Key) -> Result<(), AuthError<T::TransceiveError>> {
+        if !self.tag.supports_key(key_option) {
+            return Err(AuthError::KeyUnsupported(key_option));
+        }
+        let (auth_cmd, len) = command::auth(key_option, sector_offset.into(), key.as_bytes(), self.tag.tag_id());
 
-        let (auth_cmd_buf, len) = {
-            let tag_id = self.tag.tag_id();
-            let mut auth_cmd_buf = [cmd, sector_offset.into(), key[0], key[1], key[2], key[3], key[4], key[5], tag_id[0], tag_id[1], tag_id[2], tag_id[3], 0x00, 0x00, 0x00];
-            if tag_id.len() == 7 {
-                auth_cmd_buf[12] = tag_id[4];
-                auth_cmd_buf[13] = tag_id[5];
-                auth_cmd_buf[14] = tag_id[6];
-            };
-            (auth_cmd_buf, tag_id.len())
-        };
-        let auth_cmd = match len {
-            4 => &auth_cmd_buf[0..12],
-            7 => &auth_cmd_buf,
-            _ => unreachable!(),
-        };
+        let mut resp = [0u8; 16];
+        let len = self.transceive_raw(&auth_cmd[..len], &mut resp).map_err(AuthError::Transceive)?;
+        if !command::auth_accepted(len) {
+            return Err(AuthError::AuthenticationRejected);
+        }
+        Ok(())
+    }
+
+    /// Authenticates to sector containing `block`.
+    ///
+    /// Offsets used with the returned `AuthenticatedSector` are still relative to the start
+    /// of the sector. Use `AbsoluteBlockOffset::block_within_sector()` to get offset of
+    /// `block` (unless it's the sector trailer).
+    pub fn authenticate_block<'s, K: Into<Key>>(&'s mut self, block: AbsoluteBlockOffset<Cap>, key_option: KeyOption, key: K) -> Result<AuthenticatedSector<'s, T, Cap>, AuthError<T::TransceiveError>> {
+        self.authenticate_sector(block.sector_offset(), key_option, key)
+    }
+
+    /// Tries to authenticate to sector using each candidate key as key A and key B.
+    ///
+    /// Returns the first combination that worked or `None` if none did. The tag is selected
+    /// again after each failed attempt (see `reselect()`). If the backend can't do it, this
+    /// fails with `ReselectUnsupported` after the first failed attempt, because the
+    /// remaining candidates can't be tried. Since some backends report rejected key as
+    /// communication error, all failed authentications count as wrong key; only failure to
+    /// select the tag again is returned as error. Key B isn't tried if the backend can't use
+    /// it (see `supports_key()`).
+    pub fn find_key<SN: Into<SectorBlockOffset<Cap>>, K: Into<Key> + Copy>(&mut self, sector_number: SN, candidates: &[K]) -> Result<Option<(KeyOption, Key)>, Error<T::TransceiveError>> {
+        let sector_offset = sector_number.into();
+        for key in candidates {
+            let key = (*key).into();
+            for key_option in &[KeyOption::KeyA, KeyOption::KeyB] {
+                if !self.tag.supports_key(*key_option) {
+                    continue;
+                }
+                if self.authenticate_sector(sector_offset, *key_option, key).is_ok() {
+                    return Ok(Some((*key_option, key)));
+                }
+                self.reselect()?;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Checks whether the backend can authenticate using `key_option`.
+    ///
+    /// See `NFCTag::supports_key()`.
+    pub fn supports_key(&self, key_option: KeyOption) -> bool {
+        self.tag.supports_key(key_option)
+    }
 
+    /// Selects the tag again using `NFCTag::select()`.
+    ///
+    /// Tags stop responding after failed authentication (or any other failed command) until
+    /// they're selected again, so this should be called before trying another key.
+    /// `find_key()` and `dump()` do it automatically. Fails with `ReselectUnsupported` if the
+    /// backend can't select the tag again (PN532).
+    pub fn reselect(&mut self) -> Result<(), Error<T::TransceiveError>> {
+        match self.tag.select() {
+            Some(result) => result.map_err(Error::Transceive),
+            None => Err(Error::ReselectUnsupported),
+        }
+    }
+
+    /// Sends HALT command to the tag and returns the underlying tag.
+    ///
+    /// Halted tag doesn't respond until it's woken up or the field is cycled. Tag accepts HALT
+    /// by not responding at all, so timeout is treated as success. Other errors are returned
+    /// together with the tag. If the backend can't tell timeout from other errors (see
+    /// `NFCTag::is_timeout()`), all communication errors are treated as success.
+    pub fn halt(mut self) -> Result<T, (T, Error<T::TransceiveError>)> {
         let mut resp = [0u8; 16];
-        // Empty response on success
-        try!(self.tag.transceive(auth_cmd, &mut resp));
+        let result = match self.transceive_raw(&command::HALT, &mut resp) {
+            Ok(0) => Ok(()),
+            Ok(len) => Err(Error::UnexpectedResponse { len: len }),
+            Err(Error::Transceive(error)) => match self.tag.is_timeout(&error) {
+                Some(true) | None => Ok(()),
+                Some(false) => Err(Error::Transceive(error)),
+            },
+            Err(error) => Err(error),
+        };
+        match result {
+            Ok(()) => Ok(self.tag),
+            Err(error) => Err((self.tag, error)),
+        }
+    }
+
+    /// Returns iterator over all sectors of the tag.
+    pub fn sectors(&self) -> Sectors<Cap> {
+        numerics::SectorNumber::all()
+    }
+
+    /// Returns number of blocks usable for data (excluding sector trailers and block 0).
+    pub fn data_blocks(&self) -> u16 {
+        Cap::data_blocks()
+    }
 
-        Ok(AuthenticatedSector { tag: self, sector_offset: sector_offset })
+    /// Returns number of bytes usable for data (excluding sector trailers and block 0).
+    ///
+    /// E.g. 752 for 1K tags and 3440 for 4K tags.
+    pub fn data_bytes(&self) -> u16 {
+        Cap::data_bytes()
+    }
+
+    /// Determines type of tag from SAK. Returns `None` if the backend doesn't provide SAK.
+    pub fn detect_capacity(&self) -> Option<ClassicType> {
+        self.tag.sak().and_then(ClassicType::from_sak)
     }
 
     /// Returns id of underlying tag.
     pub fn tag_id(&self) -> &[u8] {
         self.tag.tag_id()
     }
+
+    /// Returns UID of underlying tag.
+    pub fn uid(&self) -> Uid {
+        // Length was checked in new()
+        Uid::from_slice(self.tag.tag_id()).expect("invalid UID length")
+    }
+
+    /// Checks that BCC received during anticollision matches the UID.
+    ///
+    /// Returns `None` if the backend doesn't provide BCC. Mismatch means that the UID was
+    /// probably received corrupted and the tag should be selected again.
+    pub fn verify_bcc(&self) -> Option<bool> {
+        let uid = self.uid();
+        self.tag.bcc().map(|bcc| uid.verify_bcc(bcc))
+    }
+
+    /// Checks whether the tag is still present.
+    ///
+    /// Uses `NFCTag::check_presence()` if the backend supports it (`MockTag` does). Otherwise
+    /// it selects the tag again using `NFCTag::select()` (WUPA and anticollision in case of
+    /// `Mfrc522Tag`), which works without authentication, but ends authenticated session, if
+    /// any. If you hold an authenticated sector, prefer `AuthenticatedSector::is_present()`.
+    ///
+    /// Returns `None` if the backend supports neither (PN532), because removal of the tag
+    /// can't be detected this way. Note that this returns `Option<bool>` rather than `bool`,
+    /// unlike `AuthenticatedSector::is_present()`, which can always fall back to reading.
+    pub fn is_present(&mut self) -> Option<bool> {
+        if let Some(present) = self.tag.check_presence() {
+            return Some(present);
+        }
+
+        self.tag.select().map(|result| result.is_ok())
+    }
+
+    // Reads block into the first 16 bytes of `buf`, checking that the whole block was received
+    fn read_block_checked(&mut self, block: u8, buf: &mut [u8]) -> Result<(), Error<T::TransceiveError>> {
+        command::check_read_buffer(buf.len())?;
+        let len = self.transceive_raw(&command::read(block), &mut buf[..command::READ_RESPONSE_LEN])?;
+        command::check_read_len(len)
+    }
 }
 
 /// Reference to authenticated sector.
 /// When sector is authenticated, you can perform reading and writing.
-pub struct AuthenticatedSector<'a, T: 'a> {
-    tag: &'a mut MifareTag<T>,
-    sector_offset: SectorBlockOffset4K,
+pub struct AuthenticatedSector<'a, T: 'a, Cap: 'a = Cap4K> {
+    tag: &'a mut MifareTag<T, Cap>,
+    sector_offset: SectorBlockOffset<Cap>,
+    key_option: KeyOption,
+    key: Key,
+    // Known access bits of the sector, checked before reading or writing data blocks
+    access_bits: Option<AccessBits>,
 }
 
-impl<'a, T: 'a + NFCTag> AuthenticatedSector<'a, T> {
+impl<'a, T: 'a + NFCTag, Cap: 'a + TagCapacity> AuthenticatedSector<'a, T, Cap> {
     /// Reads 16 bytes of data from given block
     ///
+    /// Fails with `BlockOutOfRange` if `offset` is the sector trailer (use `read_trailer()`,
+    /// `write_keys()` or `write_sector_trailer()` instead) or past it and with `AccessDenied`
+    /// if access checks are enabled (see `set_access_bits()`) and fail.
+    /// `buf` must have at least 16 bytes (`BufferTooSmall` otherwise) and on success, its
+    /// first 16 bytes are always overwritten - shorter response (e.g. NAK) results in
+    /// `UnexpectedLength`.
+    ///
     /// Warning: This interface is temporary and will change!
-    pub fn read_block(&mut self, offset: BlockOffset, buf: &mut [u8]) -> Result<(), T::TransceiveError> {
-        let read_cmd = [0x30, (self.sector_offset + offset).into()];
-        try!(self.tag.tag.transceive(&read_cmd, buf));
+    pub fn read_block(&mut self, offset: BlockOffset, buf: &mut [u8]) -> Result<(), Error<T::TransceiveError>> {
+        self.check_access(offset, AccessBits::data_readable)?;
+        let offset = self.absolute_offset(offset)?;
+        self.read_block_raw(offset, buf)
+    }
+
+    /// Reads all data blocks of the sector, optionally including the trailer
+    ///
+    /// Returns number of bytes read, which is 16 times the number of blocks read. Fails with
+    /// `BufferTooSmall` without reading anything if they don't fit into `buf`.
+    pub fn read_sector(&mut self, buf: &mut [u8], include_trailer: bool) -> Result<usize, Error<T::TransceiveError>> {
+        let block_count = if include_trailer {
+            self.sector_offset.block_count()
+        } else {
+            self.sector_offset.block_count() - 1
+        };
+        let required = block_count as usize * 16;
+        if buf.len() < required {
+            return Err(Error::BufferTooSmall { len: buf.len(), required: required });
+        }
+
+        let first_block = u8::from(self.sector_offset);
+        for (i, block_buf) in buf[..required].chunks_mut(16).enumerate() {
+            // Always valid, because we never go past the trailer
+            let offset = AbsoluteBlockOffset::new(first_block + i as u8).expect("invalid block offset");
+            self.read_block_raw(offset, block_buf)?;
+        }
+        Ok(required)
+    }
+
+    /// Checks whether the tag is still present.
+    ///
+    /// Uses `NFCTag::check_presence()` if the backend supports it, otherwise it reads the
+    /// first block of this sector. Any failure of that read ends the authenticated session:
+    /// if this returns `false`, or if access conditions don't allow the read (the tag answers
+    /// with NAK, so it's still reported as present), the tag has to be selected and the
+    /// sector authenticated again before further use.
+    pub fn is_present(&mut self) -> bool {
+        if let Some(present) = self.tag.tag.check_presence() {
+            return present;
+        }
+
+        let read_cmd = command::read(self.sector_offset.into());
+        let mut resp = [0u8; 16];
+        self.tag.transceive_raw(&read_cmd, &mut resp).is_ok()
+    }
+
+    /// Returns which key was used for authentication.
+    pub fn key_option(&self) -> KeyOption {
+        self.key_option
+    }
+
+    /// Authenticates to this sector again, e.g. with key B after reading with key A.
+    ///
+    /// On success, further operations use the new key (see `key_option()`). If the
+    /// authentication fails, the tag isn't authenticated anymore and doesn't respond until
+    /// it's selected again, so this sector shouldn't be used further.
+    pub fn reauthenticate(&mut self, key_option: KeyOption, key: &Key) -> Result<(), AuthError<T::TransceiveError>> {
+        self.tag.send_auth(self.sector_offset, key_option, key)?;
+        self.key_option = key_option;
+        self.key = *key;
         Ok(())
     }
 
-    fn write_block_raw(&mut self, offset: AbsoluteBlockOffset4K, data: &[u8; 16]) -> Result<(), T::TransceiveError> {
-        let mut write_cmd = [0; 18];
-        write_cmd[0] = 0xA0;
-        write_cmd[1] = offset.into();
-        write_cmd[2..].copy_from_slice(&*data);
+    /// Enables or disables (`None`) checking of access conditions.
+    ///
+    /// When enabled, `read_block()` and `write_block()` of data blocks fail with
+    /// `AccessDenied` without sending anything if `access_bits` don't allow the operation
+    /// with the key used for authentication. Checks are disabled by default.
+    pub fn set_access_bits(&mut self, access_bits: Option<AccessBits>) {
+        self.access_bits = access_bits;
+    }
+
+    /// Reads access bits from sector trailer and enables checking of access conditions.
+    ///
+    /// See `set_access_bits()`.
+    pub fn load_access_bits(&mut self) -> Result<AccessBits, Error<T::TransceiveError>> {
+        let access_bits = self.read_trailer()?.access_bits();
+        self.access_bits = Some(access_bits);
+        Ok(access_bits)
+    }
 
+    // Checks access conditions of data block, if they are known. Trailer isn't checked.
+    fn check_access(&self, offset: BlockOffset, allowed: fn(&AccessBits, usize, KeyOption) -> bool) -> Result<(), Error<T::TransceiveError>> {
+        let access_bits = match self.access_bits {
+            Some(ref access_bits) => access_bits,
+            None => return Ok(()),
+        };
+        let block_count = self.sector_offset.block_count();
+        let block = u8::from(offset);
+        if block + 1 >= block_count {
+            return Ok(());
+        }
+        // Large sectors have groups of five blocks
+        let group = if block_count == 4 { block } else { block / 5 };
+        if allowed(access_bits, group as usize, self.key_option) {
+            Ok(())
+        } else {
+            Err(Error::AccessDenied(offset))
+        }
+    }
+
+    /// Returns position of the first block of this sector.
+    pub fn sector_offset(&self) -> SectorBlockOffset<Cap> {
+        self.sector_offset
+    }
+
+    /// Converts offset of data block within this sector to absolute block offset.
+    ///
+    /// Returns `None` if `offset` is the sector trailer or past it.
+    pub fn absolute_block(&self, offset: BlockOffset) -> Option<AbsoluteBlockOffset<Cap>> {
+        self.sector_offset.checked_add(offset)
+    }
+
+    // Converts offset within this sector to absolute one
+    fn absolute_offset(&self, offset: BlockOffset) -> Result<AbsoluteBlockOffset<Cap>, Error<T::TransceiveError>> {
+        self.absolute_block(offset).ok_or(Error::BlockOutOfRange(offset))
+    }
+
+    // Converts absolute offset of data block of this sector to offset within this sector
+    fn relative_offset(&self, block: AbsoluteBlockOffset<Cap>) -> Result<BlockOffset, Error<T::TransceiveError>> {
+        if u8::from(block.sector_offset()) != u8::from(self.sector_offset) {
+            return Err(Error::AbsoluteBlockOutOfRange(block.into()));
+        }
+        block.block_within_sector().ok_or(Error::AbsoluteBlockOutOfRange(block.into()))
+    }
+
+    /// Reads 16 bytes of data from block given by its absolute offset
+    ///
+    /// Fails with `AbsoluteBlockOutOfRange` if the block isn't data block of this sector.
+    /// Otherwise it behaves like `read_block()`.
+    pub fn read_block_abs(&mut self, block: AbsoluteBlockOffset<Cap>, buf: &mut [u8]) -> Result<(), Error<T::TransceiveError>> {
+        let offset = self.relative_offset(block)?;
+        self.check_access(offset, AccessBits::data_readable)?;
+        self.read_block_raw(block, buf)
+    }
+
+    /// Writes 16 bytes of data to block given by its absolute offset
+    ///
+    /// Fails with `AbsoluteBlockOutOfRange` if the block isn't data block of this sector.
+    /// Otherwise it behaves like `write_block()`.
+    ///
+    /// WARNING: NOT tested!!! Use at your own risk! By writing incorrect values, you may
+    /// permanently damage the tag!
+    pub fn write_block_abs(&mut self, block: AbsoluteBlockOffset<Cap>, data: &[u8; 16]) -> Result<(), Error<T::TransceiveError>> {
+        let offset = self.relative_offset(block)?;
+        self.check_access(offset, AccessBits::data_writable)?;
+        self.write_block_raw(block, data)
+    }
+
+    fn read_block_raw(&mut self, offset: AbsoluteBlockOffset<Cap>, buf: &mut [u8]) -> Result<(), Error<T::TransceiveError>> {
+        self.tag.read_block_checked(offset.into(), buf)
+    }
+
+    fn write_block_raw(&mut self, offset: AbsoluteBlockOffset<Cap>, data: &[u8; 16]) -> Result<(), Error<T::TransceiveError>> {
+        if self.tag.dry_run {
+            info!("dry run: would write block {}: {:02x?}", u8::from(offset), data);
+            return Ok(());
+        }
+
+        let write_cmd = command::write(offset.into(), data);
         let mut resp = [0; 16];
-        try!(self.tag.tag.transceive(&write_cmd, &mut resp));
-        Ok(())
+        let len = self.tag.transceive_raw(&write_cmd, &mut resp)?;
+        command::check_ack(&resp[..len])
     }
 
     /// Writes 16 bytes of data to given block
     ///
+    /// Fails with `BlockOutOfRange` if `offset` is the sector trailer (use `read_trailer()`,
+    /// `write_keys()` or `write_sector_trailer()` instead) or past it and with `AccessDenied`
+    /// if access checks are enabled (see `set_access_bits()`) and fail.
+    ///
     /// WARNING: NOT tested!!! Use at your own risk! By writing incorrect values, you may
     /// permanently damage the tag!
     /// This interface is temporary and will change!
-    pub fn write_block(&mut self, offset: BlockOffset, data: &[u8; 16]) -> Result<(), T::TransceiveError> {
-        let offset = self.sector_offset + offset;
+    pub fn write_block(&mut self, offset: BlockOffset, data: &[u8; 16]) -> Result<(), Error<T::TransceiveError>> {
+        self.check_access(offset, AccessBits::data_writable)?;
+        let offset = self.absolute_offset(offset)?;
         self.write_block_raw(offset, data)
     }
 
+    /// Writes blocks yielded by iterator to consecutive blocks starting at `start`
+    ///
+    /// Returns number of written blocks. This never writes the sector trailer - if the
+    /// iterator yields more blocks than fit, it fails with `TrailerReached`. If `start` is
+    /// past the trailer, it fails with `BlockOutOfRange` without writing anything.
+    ///
+    /// WARNING: NOT tested!!! Use at your own risk! By writing incorrect values, you may
+    /// permanently damage the tag!
+    /// This interface is temporary and will change!
+    pub fn write_blocks<I: IntoIterator<Item=[u8; 16]>>(&mut self, start: BlockOffset, blocks: I) -> Result<usize, WriteBlocksError<T::TransceiveError>> {
+        let trailer = self.sector_offset.block_count() - 1;
+        let first = u8::from(start);
+        if first > trailer {
+            return Err(WriteBlocksError::BlockOutOfRange(start));
+        }
+
+        let mut written = 0;
+        for (i, block) in blocks.into_iter().enumerate() {
+            let offset = first as usize + i;
+            if offset >= trailer as usize {
+                return Err(WriteBlocksError::TrailerReached { written: written });
+            }
+            // Always valid because trailer offset is at most 15
+            let block_offset = BlockOffset::new(offset as u8).expect("invalid block offset");
+            if let Err(error) = self.write_block(block_offset, &block) {
+                return Err(WriteBlocksError::Transceive { written: written, offset: block_offset, error: error });
+            }
+            written = i + 1;
+        }
+        Ok(written)
+    }
+
+    // Sends increment, decrement or restore command. These only modify internal register of
+    // the tag, transfer_block() has to be called to store the result.
+    fn value_command(&mut self, cmd: u8, offset: BlockOffset, operand: u32) -> Result<(), Error<T::TransceiveError>> {
+        let offset = self.absolute_offset(offset)?;
+        if self.tag.dry_run {
+            info!("dry run: would send value command {:02X} to block {} with operand {}", cmd, u8::from(offset), operand);
+            return Ok(());
+        }
+
+        let value_cmd = command::value(cmd, offset.into(), operand);
+        let mut resp = [0; 16];
+        let len = self.tag.transceive_raw(&value_cmd, &mut resp)?;
+        command::check_ack(&resp[..len])
+    }
+
+    /// Reads value block at `offset` and returns its value.
+    ///
+    /// Fails with `InvalidValueBlock` if the redundant copies of value or address don't
+    /// match, which means the block doesn't contain value block.
+    pub fn read_value(&mut self, offset: BlockOffset) -> Result<i32, ValueReadError<T::TransceiveError>> {
+        let mut buf = [0; 16];
+        self.read_block(offset, &mut buf).map_err(ValueReadError::Transceive)?;
+        ValueBlock::from_bytes(&buf).map(|block| block.value()).map_err(ValueReadError::InvalidValueBlock)
+    }
+
+    /// Increments value block at `offset` by `value` and stores the result back.
+    ///
+    /// The block must already be formatted as a value block.
+    pub fn increment_block(&mut self, offset: BlockOffset, value: u32) -> Result<(), Error<T::TransceiveError>> {
+        self.value_command(0xC1, offset, value)?;
+        self.transfer_block(offset)
+    }
+
+    /// Decrements value block at `offset` by `value` and stores the result back.
+    ///
+    /// The block must already be formatted as a value block.
+    pub fn decrement_block(&mut self, offset: BlockOffset, value: u32) -> Result<(), Error<T::TransceiveError>> {
+        self.value_command(0xC0, offset, value)?;
+        self.transfer_block(offset)
+    }
+
+    /// Copies value block at `source` to `destination`.
+    ///
+    /// The tag refuses transfers between sectors. Both offsets are relative to this sector,
+    /// so such transfer can't be expressed and doesn't have to be checked. The source block
+    /// must already be formatted as a value block.
+    pub fn restore_block(&mut self, source: BlockOffset, destination: BlockOffset) -> Result<(), Error<T::TransceiveError>> {
+        self.value_command(0xC2, source, 0)?;
+        self.transfer_block(destination)
+    }
+
+    /// Stores the internal register of the tag to `destination` block.
+    ///
+    /// This is the second step of increment, decrement and restore, so it's only useful if
+    /// you want to store the result into different block than the one used as source.
+    /// Same as in `restore_block()`, `destination` is always in the same sector as source.
+    pub fn transfer_block(&mut self, destination: BlockOffset) -> Result<(), Error<T::TransceiveError>> {
+        let offset = self.absolute_offset(destination)?;
+        if self.tag.dry_run {
+            info!("dry run: would transfer value to block {}", u8::from(offset));
+            return Ok(());
+        }
+
+        let transfer_cmd = command::transfer(offset.into());
+        let mut resp = [0; 16];
+        let len = self.tag.transceive_raw(&transfer_cmd, &mut resp)?;
+        command::check_ack(&resp[..len])
+    }
+
     /// Writes keys as well as access bits
     ///
     /// WARNING: NOT tested!!! Use at your own risk! By writing incorrect values, you may
     /// permanently damage the tag!
     /// This interface is temporary and will change!
-    pub fn write_keys(&mut self, data: &[u8; 16]) -> Result<(), T::TransceiveError> {
+    pub fn write_keys(&mut self, data: &[u8; 16]) -> Result<(), Error<T::TransceiveError>> {
         let offset = self.sector_offset.sector_trailer();
         self.write_block_raw(offset, data)
     }
+
+    /// Writes sector trailer composed of keys and access bits
+    ///
+    /// If a key is `None`, the current one is kept. That's possible only for the key used
+    /// for authentication or for key B if access bits allow reading it.
+    ///
+    /// Unless `allow_lockout` is true, this refuses to write access bits that would prevent
+    /// changing them later using the key used for authentication.
+    ///
+    /// WARNING: NOT tested!!! Use at your own risk! By writing incorrect values, you may
+    /// permanently damage the tag!
+    pub fn write_sector_trailer(&mut self, key_a: Option<Key>, key_b: Option<Key>, access_bits: AccessBits, allow_lockout: bool) -> Result<(), Error<T::TransceiveError>> {
+        if !allow_lockout && !access_bits.trailer().access_bits_writable(self.key_option) {
+            return Err(Error::WouldLockOut);
+        }
+
+        let key_a = match (key_a, self.key_option) {
+            (Some(key), _) => key,
+            (None, KeyOption::KeyA) => self.key,
+            (None, KeyOption::KeyB) => return Err(Error::UnknownKey(KeyOption::KeyA)),
+        };
+        let key_b = match (key_b, self.key_option) {
+            (Some(key), _) => key,
+            (None, KeyOption::KeyB) => self.key,
+            (None, KeyOption::KeyA) => self.read_key_b()?,
+        };
+
+        let mut trailer = [0; 16];
+        trailer[0..6].copy_from_slice(key_a.as_bytes());
+        trailer[6..10].copy_from_slice(&access_bits.to_bytes());
+        trailer[10..16].copy_from_slice(key_b.as_bytes());
+
+        let offset = self.sector_offset.sector_trailer();
+        self.write_block_raw(offset, &trailer)
+    }
+
+    /// Initializes the sector - fills all data blocks with zeros and writes sector trailer
+    ///
+    /// Large sectors of 4K tags have 15 data blocks, the others 3. Manufacturer block
+    /// (block 0) is left untouched. Access bits that would prevent changing them later using
+    /// the key used for authentication are refused with `WouldLockOut` before writing
+    /// anything.
+    ///
+    /// WARNING: NOT tested!!! Use at your own risk! By writing incorrect values, you may
+    /// permanently damage the tag!
+    pub fn format_sector(&mut self, key_a: &Key, key_b: &Key, access_bits: AccessBits) -> Result<(), Error<T::TransceiveError>> {
+        if !access_bits.trailer().access_bits_writable(self.key_option) {
+            return Err(Error::WouldLockOut);
+        }
+
+        let first_block = if u8::from(self.sector_offset) == 0 { 1 } else { 0 };
+        for offset in first_block..(self.sector_offset.block_count() - 1) {
+            // Always valid, because data blocks have offsets below 15
+            let offset = BlockOffset::new(offset).expect("invalid block offset");
+            self.write_block(offset, &[0; 16])?;
+        }
+        self.write_sector_trailer(Some(*key_a), Some(*key_b), access_bits, false)
+    }
+
+    /// Reads and decodes the sector trailer.
+    ///
+    /// See `SectorTrailer::from_bytes()` for how unreadable keys are represented. Fails with
+    /// `InvalidAccessBits` if the inverted copy of access bits doesn't match.
+    pub fn read_trailer(&mut self) -> Result<SectorTrailer, Error<T::TransceiveError>> {
+        let offset = self.sector_offset.sector_trailer();
+        let mut trailer = [0; 16];
+        self.read_block_raw(offset, &mut trailer)?;
+        SectorTrailer::from_bytes(&trailer).map_err(Error::InvalidAccessBits)
+    }
+
+    // Reads key B from trailer, if current access bits allow it.
+    fn read_key_b(&mut self) -> Result<Key, Error<T::TransceiveError>> {
+        match self.read_trailer() {
+            Ok(trailer) => trailer.key_b().ok_or(Error::UnknownKey(KeyOption::KeyB)),
+            Err(Error::InvalidAccessBits(_)) => Err(Error::UnknownKey(KeyOption::KeyB)),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::{MockTag, MockError};
+
+    fn mock_tag() -> MockTag {
+        MockTag::new(Uid::Single([0x01, 0x02, 0x03, 0x04]))
+    }
+
+    // Tag which stops responding after `remaining` commands
+    struct FailingTag {
+        tag: MockTag,
+        remaining: usize,
+    }
+
+    impl NFCTag for FailingTag {
+        type TransceiveError = MockError;
+
+        fn tag_id(&self) -> &[u8] {
+            NFCTag::tag_id(&self.tag)
+        }
+
+        fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Self::TransceiveError> {
+            if self.remaining == 0 {
+                return Err(MockError::NoResponse);
+            }
+            self.remaining -= 1;
+            NFCTag::transceive(&mut self.tag, data_to_tag, data_from_tag)
+        }
+    }
+
+    // Tag reporting arbitrary SAK
+    struct SakTag {
+        tag: MockTag,
+        sak: u8,
+    }
+
+    impl NFCTag for SakTag {
+        type TransceiveError = MockError;
+
+        fn tag_id(&self) -> &[u8] {
+            NFCTag::tag_id(&self.tag)
+        }
+
+        fn sak(&self) -> Option<u8> {
+            Some(self.sak)
+        }
+
+        fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Self::TransceiveError> {
+            NFCTag::transceive(&mut self.tag, data_to_tag, data_from_tag)
+        }
+    }
+
+    #[test]
+    fn halt_returns_tag() {
+        let tag = MifareTag::new(mock_tag()).unwrap();
+        let mut mock = match tag.halt() {
+            Ok(mock) => mock,
+            Err((_, error)) => panic!("unexpected error: {:?}", error),
+        };
+        // Halted tag doesn't respond until selected again
+        assert_eq!(NFCTag::transceive(&mut mock, &[0x30, 0x00], &mut [0; 16]), Err(MockError::NoResponse));
+
+        mock.set_present(false);
+        match MifareTag::new(mock).unwrap().halt() {
+            Err((mock, Error::Transceive(MockError::NotPresent))) => assert_eq!(NFCTag::tag_id(&mock), [0x01, 0x02, 0x03, 0x04]),
+            Err((_, error)) => panic!("unexpected error: {:?}", error),
+            Ok(_) => panic!("removed tag halted"),
+        }
+    }
+
+    #[test]
+    fn bcc_is_checked_only_on_request() {
+        assert_eq!(MifareTag::new(mock_tag()).unwrap().verify_bcc(), Some(true));
+        assert!(MifareTag::new_checked(mock_tag()).is_some());
+
+        // Correct BCC of 01020304 is 04
+        let mut mock = mock_tag();
+        mock.set_bcc([0x05, 0x00]);
+        let tag = MifareTag::new(mock).unwrap();
+        assert_eq!(tag.verify_bcc(), Some(false));
+        assert!(MifareTag::new_checked(tag.tag).is_none());
+
+        let mut mock = MockTag::new(Uid::Double([0x04, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66]));
+        mock.set_bcc([0xBF, 0x44]);
+        assert!(MifareTag::new_checked(mock).is_some());
+        let mut mock = MockTag::new(Uid::Double([0x04, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66]));
+        mock.set_bcc([0xBF, 0x45]);
+        assert!(MifareTag::new_checked(mock).is_none());
+    }
+
+    #[test]
+    fn sak_is_checked_only_on_request() {
+        // Mifare DESFire
+        assert!(MifareTag::new(SakTag { tag: mock_tag(), sak: 0x20 }).is_some());
+        assert!(MifareTag::new_classic(SakTag { tag: mock_tag(), sak: 0x20 }).is_none());
+        assert!(MifareTag::<_, Cap1K>::with_capacity_classic(SakTag { tag: mock_tag(), sak: 0x20 }).is_none());
+
+        let tag = MifareTag::new_classic(SakTag { tag: mock_tag(), sak: 0x08 }).unwrap();
+        assert_eq!(tag.detect_capacity(), Some(ClassicType::Classic1K));
+    }
+
+    #[test]
+    fn data_operations_refuse_trailer() {
+        let mut tag = MifareTag::new(mock_tag()).unwrap();
+        let mut sector = tag.authenticate_sector(SectorNumber4K::new(1).unwrap(), KeyOption::KeyA, Key::DEFAULT).unwrap();
+        let trailer = BlockOffset::new(3).unwrap();
+        let mut buf = [0; 16];
+        match sector.write_block(trailer, &[0; 16]) {
+            Err(Error::BlockOutOfRange(offset)) => assert_eq!(offset, trailer),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match sector.read_block(trailer, &mut buf) {
+            Err(Error::BlockOutOfRange(_)) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match sector.increment_block(trailer, 1) {
+            Err(Error::BlockOutOfRange(_)) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(sector.absolute_block(trailer).is_none());
+
+        let image = tag.tag.image();
+        assert_eq!(image[112..118], [0xFF; 6]);
+        assert_eq!(image[118..122], AccessBits::TRANSPORT.to_bytes());
+    }
+
+    #[test]
+    fn absolute_operations_refuse_other_blocks() {
+        let mut tag = MifareTag::new(mock_tag()).unwrap();
+        let mut sector = tag.authenticate_sector(SectorNumber4K::new(1).unwrap(), KeyOption::KeyA, Key::DEFAULT).unwrap();
+        let mut buf = [0; 16];
+        for &block in &[3, 7, 8] {
+            let block = AbsoluteBlockOffset::new(block).unwrap();
+            match sector.write_block_abs(block, &[0; 16]) {
+                Err(Error::AbsoluteBlockOutOfRange(offset)) => assert_eq!(offset, u8::from(block)),
+                result => panic!("unexpected result: {:?}", result),
+            }
+            match sector.read_block_abs(block, &mut buf) {
+                Err(Error::AbsoluteBlockOutOfRange(_)) => (),
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+        sector.write_block_abs(AbsoluteBlockOffset::new(6).unwrap(), &[0x66; 16]).unwrap();
+        sector.read_block_abs(AbsoluteBlockOffset::new(6).unwrap(), &mut buf).unwrap();
+        assert_eq!(buf, [0x66; 16]);
+
+        let image = tag.tag.image();
+        // Trailers of sectors 0 and 1 are untouched
+        assert_eq!(image[48..54], [0xFF; 6]);
+        assert_eq!(image[112..118], [0xFF; 6]);
+        assert_eq!(image[96..112], [0x66; 16]);
+        assert_eq!(image[128..144], [0; 16]);
+    }
+
+    #[test]
+    fn large_sector_has_15_data_blocks() {
+        let mut tag = MifareTag::new(mock_tag()).unwrap();
+        let mut sector = tag.authenticate_sector(SectorNumber4K::new(32).unwrap(), KeyOption::KeyA, Key::DEFAULT).unwrap();
+        sector.write_block(BlockOffset::new(3).unwrap(), &[0x33; 16]).unwrap();
+        sector.write_block(BlockOffset::new(14).unwrap(), &[0x44; 16]).unwrap();
+
+        let image = tag.tag.image();
+        assert_eq!(image[(131 * 16)..(132 * 16)], [0x33; 16]);
+        assert_eq!(image[(142 * 16)..(143 * 16)], [0x44; 16]);
+        assert_eq!(image[(143 * 16)..(143 * 16 + 6)], [0xFF; 6]);
+    }
+
+    #[test]
+    fn write_blocks_reports_partial_failure() {
+        // Authentication and two writes succeed
+        let mut tag = MifareTag::new(FailingTag { tag: mock_tag(), remaining: 3 }).unwrap();
+        let mut sector = tag.authenticate_sector(SectorNumber4K::new(1).unwrap(), KeyOption::KeyA, Key::DEFAULT).unwrap();
+        let blocks = [[0x11; 16], [0x22; 16], [0x33; 16]];
+        match sector.write_blocks(BlockOffset::new(0).unwrap(), blocks.iter().cloned()) {
+            Err(WriteBlocksError::Transceive { written: 2, offset, error: Error::Transceive(MockError::NoResponse) }) => assert_eq!(u8::from(offset), 2),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let image = tag.tag.tag.image();
+        assert_eq!(image[64..80], [0x11; 16]);
+        assert_eq!(image[80..96], [0x22; 16]);
+        assert_eq!(image[96..112], [0; 16]);
+    }
+
+    #[test]
+    fn write_blocks_stops_before_trailer() {
+        let mut tag = MifareTag::new(mock_tag()).unwrap();
+        let mut sector = tag.authenticate_sector(SectorNumber4K::new(1).unwrap(), KeyOption::KeyA, Key::DEFAULT).unwrap();
+        let blocks = [[0x11; 16]; 3];
+        match sector.write_blocks(BlockOffset::new(1).unwrap(), blocks.iter().cloned()) {
+            Err(WriteBlocksError::TrailerReached { written: 2 }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let image = tag.tag.image();
+        assert_eq!(image[80..112], [0x11; 32]);
+        assert_eq!(image[112..118], [0xFF; 6]);
+    }
+
+    #[test]
+    fn write_blocks_fills_large_sector() {
+        let mut tag = MifareTag::new(mock_tag()).unwrap();
+        let mut sector = tag.authenticate_sector(SectorNumber4K::new(32).unwrap(), KeyOption::KeyA, Key::DEFAULT).unwrap();
+        let blocks = [[0x11; 16]; 15];
+        assert_eq!(sector.write_blocks(BlockOffset::new(0).unwrap(), blocks.iter().cloned()).unwrap(), 15);
+    }
+
+    #[test]
+    fn dry_run_sends_no_value_commands() {
+        // Only authentication is answered, any other command fails
+        let mut tag = MifareTag::new(FailingTag { tag: mock_tag(), remaining: 1 }).unwrap();
+        tag.set_dry_run(true);
+        let mut sector = tag.authenticate_sector(SectorNumber4K::new(1).unwrap(), KeyOption::KeyA, Key::DEFAULT).unwrap();
+        let offset = BlockOffset::new(1).unwrap();
+        sector.increment_block(offset, 1).unwrap();
+        sector.decrement_block(offset, 1).unwrap();
+        sector.restore_block(offset, BlockOffset::new(2).unwrap()).unwrap();
+        assert_eq!(tag.tag.remaining, 0);
+    }
+
+    #[test]
+    fn refused_write_is_nak() {
+        let mut mock = mock_tag();
+        // Data blocks are read-only, trailer is writable only with key B
+        let access_bits = AccessBits::new([AccessCondition::from_bits(0b010).unwrap(); 3], AccessCondition::from_bits(0b011).unwrap(), 0x69);
+        mock.image_mut()[(7 * 16 + 6)..(7 * 16 + 10)].copy_from_slice(&access_bits.to_bytes());
+        let mut tag = MifareTag::new(mock).unwrap();
+        let mut sector = tag.authenticate_sector(SectorNumber4K::new(1).unwrap(), KeyOption::KeyA, Key::DEFAULT).unwrap();
+        match sector.write_block(BlockOffset::new(1).unwrap(), &[0x11; 16]) {
+            Err(Error::NakReceived(0x4)) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match sector.write_sector_trailer(Some(Key::DEFAULT), Some(Key::DEFAULT), access_bits, true) {
+            Err(Error::NakReceived(0x4)) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(tag.tag.image()[80..96], [0; 16]);
+    }
+
+    #[test]
+    fn removed_tag_isnt_present() {
+        let mut tag = MifareTag::new(mock_tag()).unwrap();
+        assert_eq!(tag.is_present(), Some(true));
+        tag.tag.set_present(false);
+        assert_eq!(tag.is_present(), Some(false));
+        tag.tag.set_present(true);
+        let mut sector = tag.authenticate_sector(SectorNumber4K::new(1).unwrap(), KeyOption::KeyA, Key::DEFAULT).unwrap();
+        assert!(sector.is_present());
+    }
+
+    #[test]
+    fn presence_is_unknown_without_check_or_select() {
+        let mut tag = MifareTag::new(SakTag { tag: mock_tag(), sak: 0x18 }).unwrap();
+        assert_eq!(tag.is_present(), None);
+        // Authenticated sector reads its first block instead
+        let mut sector = tag.authenticate_sector(SectorNumber4K::new(1).unwrap(), KeyOption::KeyA, Key::DEFAULT).unwrap();
+        assert!(sector.is_present());
+        sector.tag.tag.tag.set_present(false);
+        assert!(!sector.is_present());
+    }
+
+    #[test]
+    fn find_key_selects_tag_after_failed_attempt() {
+        let key = Key::new([0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5]);
+        let mut mock = mock_tag();
+        mock.set_key_a(1, key);
+        mock.set_key_b(1, key);
+        let mut tag = MifareTag::new(mock).unwrap();
+        let found = tag.find_key(SectorNumber4K::new(1).unwrap(), &[Key::new([0; 6]), key]).unwrap();
+        assert_eq!(found, Some((KeyOption::KeyA, key)));
+        assert_eq!(tag.find_key(SectorNumber4K::new(1).unwrap(), &[Key::new([0; 6])]).unwrap(), None);
+    }
+
+    #[test]
+    fn find_key_fails_without_reselect() {
+        let key = Key::new([0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5]);
+        let mut mock = mock_tag();
+        mock.set_key_a(1, key);
+        // SakTag doesn't implement select()
+        let mut tag = MifareTag::new(SakTag { tag: mock, sak: 0x18 }).unwrap();
+        match tag.find_key(SectorNumber4K::new(1).unwrap(), &[Key::new([0; 6]), key]) {
+            Err(Error::ReselectUnsupported) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    // Tag whose backend can authenticate only with key A
+    struct KeyATag(MockTag);
+
+    impl NFCTag for KeyATag {
+        type TransceiveError = MockError;
+
+        fn tag_id(&self) -> &[u8] {
+            NFCTag::tag_id(&self.0)
+        }
+
+        fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Self::TransceiveError> {
+            NFCTag::transceive(&mut self.0, data_to_tag, data_from_tag)
+        }
+
+        fn supports_key(&self, key_option: KeyOption) -> bool {
+            key_option == KeyOption::KeyA
+        }
+
+        fn select(&mut self) -> Option<Result<(), Self::TransceiveError>> {
+            self.0.select()
+        }
+    }
+
+    #[test]
+    fn unsupported_key_isnt_sent() {
+        let mut tag = MifareTag::new(KeyATag(mock_tag())).unwrap();
+        assert!(!tag.supports_key(KeyOption::KeyB));
+        match tag.authenticate_sector(SectorNumber4K::new(1).unwrap(), KeyOption::KeyB, Key::DEFAULT) {
+            Err(AuthError::KeyUnsupported(KeyOption::KeyB)) => (),
+            Err(error) => panic!("unexpected error: {:?}", error),
+            Ok(_) => panic!("authenticated with unsupported key"),
+        }
+        // The tag would be halted if the authentication was sent
+        tag.authenticate_sector(SectorNumber4K::new(1).unwrap(), KeyOption::KeyA, Key::DEFAULT).unwrap();
+    }
+
+    #[test]
+    fn find_key_skips_unsupported_key() {
+        let key = Key::new([0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5]);
+        let mut mock = mock_tag();
+        mock.set_key_a(1, key);
+        let mut tag = MifareTag::new(KeyATag(mock)).unwrap();
+        // Key B is still the default one
+        assert_eq!(tag.find_key(SectorNumber4K::new(1).unwrap(), &[Key::DEFAULT]).unwrap(), None);
+        assert_eq!(tag.find_key(SectorNumber4K::new(1).unwrap(), &[Key::DEFAULT, key]).unwrap(), Some((KeyOption::KeyA, key)));
+    }
+
+    #[test]
+    fn write_blocks_rejects_start_past_trailer() {
+        let mut tag = MifareTag::new(mock_tag()).unwrap();
+        let mut sector = tag.authenticate_sector(SectorNumber4K::new(1).unwrap(), KeyOption::KeyA, Key::DEFAULT).unwrap();
+        match sector.write_blocks(BlockOffset::new(5).unwrap(), ::core::iter::empty()) {
+            Err(WriteBlocksError::BlockOutOfRange(offset)) => assert_eq!(u8::from(offset), 5),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
 }
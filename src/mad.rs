@@ -0,0 +1,209 @@
+use core::fmt;
+use crate::{NFCTag, MifareTag, Key, KeyOption, DumpError};
+use crate::numerics::{TagCapacity, SectorNumber, SectorNumber4K};
+
+/// Key A of MAD sectors (0 and 16).
+pub const MAD_KEY: Key = Key::new([0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5]);
+
+/// Application ID of NDEF sectors.
+pub const NDEF_AID: u16 = 0xE103;
+
+// 4K tags store the second part of MAD (MAD2) in sector 16
+const MAD2_SECTOR: u8 = 16;
+
+// Bits of general purpose byte (byte 9 of sector 0 trailer)
+const GPB_MAD_AVAILABLE: u8 = 0x80;
+const GPB_VERSION_MASK: u8 = 0x03;
+
+/// Error returned when decoding invalid MAD.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MadError {
+    /// General purpose byte in trailer of sector 0 says the tag has no MAD.
+    NotPresent,
+    /// MAD version isn't 1 or 2.
+    UnsupportedVersion(u8),
+    /// CRC of MAD stored in `sector` (0 or 16) doesn't match.
+    InvalidCrc { sector: u8 },
+}
+
+impl fmt::Display for MadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MadError::NotPresent => write!(f, "the tag has no MAD"),
+            MadError::UnsupportedVersion(version) => write!(f, "unsupported MAD version {}", version),
+            MadError::InvalidCrc { sector } => write!(f, "invalid CRC of MAD in sector {}", sector),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for MadError {}
+
+// CRC-8 used by MAD (polynomial 0x1D, preset 0xC7)
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0xC7u8;
+    for byte in data {
+        crc ^= *byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x1D } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Mifare Application Directory - assignment of application IDs (AIDs) to sectors.
+///
+/// Described in NXP AN10787. MAD1 (in sector 0) covers sectors 1 - 15, MAD2 (in sector 16,
+/// present only on 4K tags) covers sectors 17 - 39.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Mad {
+    // AID of each sector, None if it's not covered by MAD
+    aids: [Option<u16>; 40],
+}
+
+impl Mad {
+    /// Decodes MAD from data blocks of sector 0 (blocks 1 and 2) and optionally of sector 16
+    /// (blocks 64 - 66), checking CRCs.
+    pub fn from_bytes(mad1: &[u8; 32], mad2: Option<&[u8; 48]>) -> Result<Self, MadError> {
+        let mut aids = [None; 40];
+        Mad::decode(0, mad1, &mut aids[1..16])?;
+        if let Some(mad2) = mad2 {
+            Mad::decode(MAD2_SECTOR, mad2, &mut aids[17..40])?;
+        }
+        Ok(Mad { aids: aids })
+    }
+
+    // Checks CRC (byte 0) and decodes AIDs following the info byte (byte 1)
+    fn decode(sector: u8, data: &[u8], aids: &mut [Option<u16>]) -> Result<(), MadError> {
+        if crc8(&data[1..]) != data[0] {
+            return Err(MadError::InvalidCrc { sector: sector });
+        }
+        for (aid, bytes) in aids.iter_mut().zip(data[2..].chunks(2)) {
+            *aid = Some(bytes[0] as u16 | (bytes[1] as u16) << 8);
+        }
+        Ok(())
+    }
+
+    /// Returns AID of application in `sector`.
+    ///
+    /// Returns `None` for MAD sectors and sectors not covered by MAD. AID 0x0000 means that
+    /// the sector is free.
+    pub fn aid(&self, sector: SectorNumber4K) -> Option<u16> {
+        self.aids[u8::from(sector) as usize]
+    }
+
+    /// Returns iterator over sectors assigned to `aid`, in ascending order.
+    pub fn sectors<'a>(&'a self, aid: u16) -> impl Iterator<Item = SectorNumber4K> + 'a {
+        SectorNumber4K::all().filter(move |sector| self.aid(*sector) == Some(aid))
+    }
+}
+
+impl<T: NFCTag, Cap: TagCapacity> MifareTag<T, Cap> {
+    /// Reads and decodes MAD, authenticating using `MAD_KEY` as key A.
+    ///
+    /// MAD2 is read only if the general purpose byte says it's present and the tag has
+    /// sector 16. Decoding errors are returned as `DumpError::InvalidMad`.
+    pub fn read_mad(&mut self) -> Result<Mad, DumpError<T::TransceiveError>> {
+        // Every tag has sector 0
+        let sector0 = SectorNumber::<Cap>::new(0).expect("invalid sector number");
+        let mut sector_data = [0; 64];
+        self.dump_sector(sector0, KeyOption::KeyA, &MAD_KEY, &mut sector_data)?;
+
+        let gpb = sector_data[48 + 9];
+        if gpb & GPB_MAD_AVAILABLE == 0 {
+            return Err(DumpError::InvalidMad(MadError::NotPresent));
+        }
+        let version = gpb & GPB_VERSION_MASK;
+        if version != 1 && version != 2 {
+            return Err(DumpError::InvalidMad(MadError::UnsupportedVersion(version)));
+        }
+        let mut mad1 = [0; 32];
+        mad1.copy_from_slice(&sector_data[16..48]);
+
+        let mut mad2 = [0; 48];
+        let has_mad2 = match SectorNumber::<Cap>::new(MAD2_SECTOR) {
+            Some(sector16) if version == 2 => {
+                self.dump_sector(sector16, KeyOption::KeyA, &MAD_KEY, &mut sector_data)?;
+                mad2.copy_from_slice(&sector_data[..48]);
+                true
+            },
+            _ => false,
+        };
+
+        Mad::from_bytes(&mad1, if has_mad2 { Some(&mad2) } else { None }).map_err(DumpError::InvalidMad)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // MAD of tags formatted for NDEF (NXP AN1304), all sectors are assigned to NDEF
+    fn ndef_mad1() -> [u8; 32] {
+        let mut mad1 = [0; 32];
+        mad1[0] = 0x14;
+        mad1[1] = 0x01;
+        for aid in mad1[2..].chunks_mut(2) {
+            aid.copy_from_slice(&[0x03, 0xE1]);
+        }
+        mad1
+    }
+
+    fn ndef_mad2() -> [u8; 48] {
+        let mut mad2 = [0; 48];
+        mad2[0] = 0xE8;
+        mad2[1] = 0x01;
+        for aid in mad2[2..].chunks_mut(2) {
+            aid.copy_from_slice(&[0x03, 0xE1]);
+        }
+        mad2
+    }
+
+    fn sector(sector: u8) -> SectorNumber4K {
+        SectorNumber4K::new(sector).unwrap()
+    }
+
+    #[test]
+    fn crc_of_ndef_mad() {
+        assert_eq!(crc8(&ndef_mad1()[1..]), 0x14);
+        assert_eq!(crc8(&ndef_mad2()[1..]), 0xE8);
+        // Preset is the CRC of no data
+        assert_eq!(crc8(&[]), 0xC7);
+    }
+
+    #[test]
+    fn decode_mad1() {
+        let mut mad1 = ndef_mad1();
+        // Sector 2 is free
+        mad1[4] = 0x00;
+        mad1[5] = 0x00;
+        mad1[0] = crc8(&mad1[1..]);
+        let mad = Mad::from_bytes(&mad1, None).unwrap();
+        assert_eq!(mad.aid(sector(0)), None);
+        assert_eq!(mad.aid(sector(1)), Some(NDEF_AID));
+        assert_eq!(mad.aid(sector(2)), Some(0x0000));
+        assert_eq!(mad.aid(sector(15)), Some(NDEF_AID));
+        assert_eq!(mad.aid(sector(17)), None);
+        assert!(mad.sectors(NDEF_AID).map(u8::from).eq((1..16).filter(|sector| *sector != 2)));
+    }
+
+    #[test]
+    fn decode_mad2() {
+        let mad = Mad::from_bytes(&ndef_mad1(), Some(&ndef_mad2())).unwrap();
+        assert_eq!(mad.aid(sector(16)), None);
+        assert_eq!(mad.aid(sector(17)), Some(NDEF_AID));
+        assert_eq!(mad.aid(sector(39)), Some(NDEF_AID));
+        assert_eq!(mad.sectors(NDEF_AID).count(), 38);
+    }
+
+    #[test]
+    fn invalid_crc() {
+        let mut mad1 = ndef_mad1();
+        mad1[31] ^= 0x01;
+        assert_eq!(Mad::from_bytes(&mad1, None), Err(MadError::InvalidCrc { sector: 0 }));
+
+        let mut mad2 = ndef_mad2();
+        mad2[0] ^= 0x01;
+        assert_eq!(Mad::from_bytes(&ndef_mad1(), Some(&mad2)), Err(MadError::InvalidCrc { sector: 16 }));
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{command, NFCTag, MifareTag, Error};
+use crate::numerics::{TagCapacity, AbsoluteBlockOffset};
+
+// Gen1a backdoor commands, the first one is sent as 7 bit frame
+const GEN1A_UNLOCK_1: u8 = 0x40;
+const GEN1A_UNLOCK_2: u8 = 0x43;
+
+impl<T: NFCTag, Cap: TagCapacity> MifareTag<T, Cap> {
+    /// Unlocks magic Gen1a ("Chinese backdoor") tag.
+    ///
+    /// Halts the tag and sends the backdoor command sequence (7 bit 0x40 followed by 0x43).
+    /// Unlocked tag allows reading and writing all blocks, including block 0, without
+    /// authentication.
+    ///
+    /// This only works on magic tags! Genuine tags don't respond to the sequence, which
+    /// results in `Transceive` error or `UnexpectedResponse`. The backend has to support
+    /// sending bit frames (see `NFCTag::transceive_bits()`), otherwise this fails with
+    /// `BitFramesUnsupported`.
+    pub fn unlock_gen1a<'s>(&'s mut self) -> Result<Gen1aUnlocked<'s, T, Cap>, Error<T::TransceiveError>> {
+        // Tag doesn't answer HALT, so errors are expected
+        let mut resp = [0u8; 16];
+        let _ = self.transceive_raw(&command::HALT, &mut resp);
+
+        self.transceive_ack(&[GEN1A_UNLOCK_1], 7)?;
+        self.transceive_ack(&[GEN1A_UNLOCK_2], 8)?;
+        Ok(Gen1aUnlocked { tag: self })
+    }
+
+    // Sends bit frame and checks that the tag acknowledged it
+    fn transceive_ack(&mut self, data_to_tag: &[u8], last_byte_bits: u8) -> Result<(), Error<T::TransceiveError>> {
+        let mut resp = [0u8; 16];
+        let len = match self.tag.transceive_bits(data_to_tag, last_byte_bits, &mut resp) {
+            Some(result) => result.map_err(Error::Transceive)?,
+            None => return Err(Error::BitFramesUnsupported),
+        };
+        if len != 1 || resp[0] & 0x0F != command::ACK {
+            return Err(Error::UnexpectedResponse { len: len });
+        }
+        Ok(())
+    }
+}
+
+/// Magic Gen1a tag unlocked by `MifareTag::unlock_gen1a()`.
+///
+/// The tag stays unlocked until it's halted, authenticated or removed from the field.
+pub struct Gen1aUnlocked<'a, T: 'a, Cap: 'a> {
+    tag: &'a mut MifareTag<T, Cap>,
+}
+
+impl<'a, T: 'a + NFCTag, Cap: 'a + TagCapacity> Gen1aUnlocked<'a, T, Cap> {
+    /// Reads 16 bytes of data from any block.
+    ///
+    /// Fails with `BufferTooSmall` if `buf` is shorter than 16 bytes and with
+    /// `UnexpectedLength` if the tag doesn't return whole block.
+    pub fn read_block(&mut self, offset: AbsoluteBlockOffset<Cap>, buf: &mut [u8]) -> Result<(), Error<T::TransceiveError>> {
+        self.tag.read_block_checked(offset.into(), buf)
+    }
+
+    /// Writes 16 bytes of data to any block, including block 0 and sector trailers.
+    ///
+    /// WARNING: NOT tested!!! Use at your own risk! By writing incorrect values, you may
+    /// permanently damage the tag!
+    pub fn write_block(&mut self, offset: AbsoluteBlockOffset<Cap>, data: &[u8; 16]) -> Result<(), Error<T::TransceiveError>> {
+        if self.tag.dry_run {
+            info!("dry run: would write block {}: {:02x?}", u8::from(offset), data);
+            return Ok(());
+        }
+
+        let mut resp = [0; 16];
+        let len = self.tag.transceive_raw(&command::write(offset.into(), data), &mut resp)?;
+        command::check_ack(&resp[..len])
+    }
+
+    /// Writes manufacturer block (block 0), which contains UID.
+    ///
+    /// Note that the first four bytes of single-size UID must be followed by BCC (XOR of them),
+    /// otherwise the tag becomes unusable with most readers.
+    ///
+    /// WARNING: NOT tested!!! Use at your own risk! By writing incorrect values, you may
+    /// permanently damage the tag!
+    pub fn write_block0(&mut self, data: &[u8; 16]) -> Result<(), Error<T::TransceiveError>> {
+        // Block 0 exists on all tags
+        let offset = AbsoluteBlockOffset::new(0).expect("invalid block offset");
+        self.write_block(offset, data)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{NFCTag, MifareTag, Error};
+    use crate::mock::{MockTag, MockError};
+    use crate::numerics::AbsoluteBlockOffset;
+    use crate::uid::Uid;
+
+    fn mock_tag() -> MockTag {
+        MockTag::new(Uid::Single([0x01, 0x02, 0x03, 0x04]))
+    }
+
+    // Tag whose backend can't send bit frames
+    struct NoBitFramesTag {
+        tag: MockTag,
+    }
+
+    impl NFCTag for NoBitFramesTag {
+        type TransceiveError = MockError;
+
+        fn tag_id(&self) -> &[u8] {
+            NFCTag::tag_id(&self.tag)
+        }
+
+        fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Self::TransceiveError> {
+            NFCTag::transceive(&mut self.tag, data_to_tag, data_from_tag)
+        }
+    }
+
+    #[test]
+    fn unlock_gen1a() {
+        let mut mock = mock_tag();
+        mock.set_gen1a(true);
+        let mut tag = MifareTag::new(mock).unwrap();
+        let block0 = [0x11, 0x22, 0x33, 0x44, 0x44, 0x08, 0x04, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        {
+            let mut unlocked = tag.unlock_gen1a().unwrap();
+            unlocked.write_block0(&block0).unwrap();
+            let mut buf = [0; 16];
+            unlocked.read_block(AbsoluteBlockOffset::new(0).unwrap(), &mut buf).unwrap();
+            assert_eq!(buf, block0);
+        }
+        assert_eq!(tag.tag.image()[..16], block0);
+    }
+
+    #[test]
+    fn genuine_tag_isnt_unlocked() {
+        let mut tag = MifareTag::new(mock_tag()).unwrap();
+        match tag.unlock_gen1a() {
+            Err(Error::Transceive(MockError::NoResponse)) => (),
+            Err(error) => panic!("unexpected error: {:?}", error),
+            Ok(_) => panic!("genuine tag unlocked"),
+        }
+        assert_eq!(tag.tag.image()[..4], [0x01, 0x02, 0x03, 0x04]);
+    }
+
+    #[test]
+    fn unlock_requires_bit_frames() {
+        let mut mock = mock_tag();
+        mock.set_gen1a(true);
+        let mut tag = MifareTag::new(NoBitFramesTag { tag: mock }).unwrap();
+        match tag.unlock_gen1a() {
+            Err(Error::BitFramesUnsupported) => (),
+            Err(error) => panic!("unexpected error: {:?}", error),
+            Ok(_) => panic!("unlocked without bit frames"),
+        }
+    }
+}
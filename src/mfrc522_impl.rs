@@ -0,0 +1,113 @@
+use core::fmt;
+use mfrc522::{Mfrc522, Initialized, Uid};
+use mfrc522::comm::Interface;
+use crate::{NFCTag, KeyOption};
+
+/// Error of `Mfrc522Tag`.
+#[derive(Debug)]
+pub enum Mfrc522Error<E> {
+    /// Operation of the reader failed (this includes rejected authentication).
+    Reader(mfrc522::Error<E>),
+    /// The command (first byte) can't be performed using the `mfrc522` crate.
+    UnsupportedCommand(u8),
+    /// Selecting the tag again found a tag with different UID.
+    DifferentTag,
+}
+
+impl<E: fmt::Debug> fmt::Display for Mfrc522Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Mfrc522Error::Reader(ref error) => write!(f, "MFRC522 error: {:?}", error),
+            Mfrc522Error::UnsupportedCommand(cmd) => write!(f, "command {:02X} isn't supported by MFRC522 backend", cmd),
+            Mfrc522Error::DifferentTag => write!(f, "selected tag has different UID"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Debug> ::std::error::Error for Mfrc522Error<E> {}
+
+/// Tag selected by MFRC522 reader.
+///
+/// MFRC522 performs Crypto1 itself, so Mifare commands are mapped to methods of `Mfrc522`:
+/// authentication, reading, writing and halting are supported. Authentication with key B
+/// isn't, so `NFCTag::supports_key()` returns false for it and `MifareTag` refuses such
+/// authentication without sending anything. Value operations fail with
+/// `Mfrc522Error::UnsupportedCommand`.
+///
+/// Selecting the tag again (`NFCTag::select()`) stops Crypto1 of the reader first, otherwise
+/// WUPA would be sent encrypted after successful authentication and the tag wouldn't answer.
+pub struct Mfrc522Tag<COMM: Interface> {
+    reader: Mfrc522<COMM, Initialized>,
+    uid: Uid,
+}
+
+impl<COMM: Interface> Mfrc522Tag<COMM> {
+    /// Creates tag from reader and UID returned by `Mfrc522::select()`.
+    pub fn new(reader: Mfrc522<COMM, Initialized>, uid: Uid) -> Self {
+        Mfrc522Tag { reader: reader, uid: uid }
+    }
+
+    /// Returns the reader.
+    pub fn into_inner(self) -> Mfrc522<COMM, Initialized> {
+        self.reader
+    }
+
+    // Fails with `DifferentTag` if another tag (including one with triple-size UID, which
+    // can't be used as Mifare Classic) answered, keeping the UID of the original one
+    fn wake_up_and_select(&mut self) -> Result<(), Mfrc522Error<COMM::Error>> {
+        // The reader keeps encrypting after authentication, even if the tag is gone
+        self.reader.stop_crypto1().map_err(Mfrc522Error::Reader)?;
+        // WUPA wakes up the tag even if it's halted
+        let atqa = self.reader.wupa().map_err(Mfrc522Error::Reader)?;
+        let uid = self.reader.select(&atqa).map_err(Mfrc522Error::Reader)?;
+        if uid.as_bytes() != self.uid.as_bytes() {
+            return Err(Mfrc522Error::DifferentTag);
+        }
+        Ok(())
+    }
+}
+
+impl<COMM: Interface> NFCTag for Mfrc522Tag<COMM> where COMM::Error: fmt::Debug {
+    type TransceiveError = Mfrc522Error<COMM::Error>;
+
+    fn tag_id(&self) -> &[u8] {
+        self.uid.as_bytes()
+    }
+
+    fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Self::TransceiveError> {
+        match (data_to_tag.first().cloned(), data_to_tag.len()) {
+            (Some(0x60), len) if len >= 8 => {
+                let mut key = [0; 6];
+                key.copy_from_slice(&data_to_tag[2..8]);
+                self.reader.mf_authenticate(&self.uid, data_to_tag[1], &key).map_err(Mfrc522Error::Reader)?;
+                Ok(0)
+            },
+            (Some(0x30), 2) => {
+                let data = self.reader.mf_read(data_to_tag[1]).map_err(Mfrc522Error::Reader)?;
+                let len = ::core::cmp::min(data.len(), data_from_tag.len());
+                data_from_tag[..len].copy_from_slice(&data[..len]);
+                Ok(data.len())
+            },
+            (Some(0xA0), 18) => {
+                let mut data = [0; 16];
+                data.copy_from_slice(&data_to_tag[2..]);
+                self.reader.mf_write(data_to_tag[1], data).map_err(Mfrc522Error::Reader)?;
+                Ok(0)
+            },
+            (Some(0x50), 2) => {
+                self.reader.hlta().map_err(Mfrc522Error::Reader)?;
+                Ok(0)
+            },
+            (cmd, _) => Err(Mfrc522Error::UnsupportedCommand(cmd.unwrap_or(0))),
+        }
+    }
+
+    fn supports_key(&self, key_option: KeyOption) -> bool {
+        key_option == KeyOption::KeyA
+    }
+
+    fn select(&mut self) -> Option<Result<(), Self::TransceiveError>> {
+        Some(self.wake_up_and_select())
+    }
+}
@@ -0,0 +1,401 @@
+use crate::{NFCTag, Key, KeyOption};
+use crate::uid::Uid;
+use crate::value::ValueBlock;
+use crate::access::AccessBits;
+use crate::numerics::{AbsoluteBlockOffset, SectorBlockOffset, Cap4K};
+
+/// Error returned by MockTag.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MockError {
+    /// The tag was removed using `set_present(false)`.
+    NotPresent,
+    /// The accessed block isn't in authenticated sector.
+    NotAuthenticated,
+    /// The command is malformed or refers to invalid block.
+    InvalidCommand,
+    /// The command isn't supported by MockTag.
+    UnsupportedCommand(u8),
+    /// Response buffer is too small.
+    BufferTooSmall,
+    /// The tag didn't respond, which is what genuine tags do to backdoor commands and halted
+    /// tags to any command.
+    NoResponse,
+}
+
+impl ::core::fmt::Display for MockError {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        match *self {
+            MockError::NotPresent => write!(f, "tag is not present"),
+            MockError::NotAuthenticated => write!(f, "block is not in authenticated sector"),
+            MockError::InvalidCommand => write!(f, "invalid command"),
+            MockError::UnsupportedCommand(cmd) => write!(f, "unsupported command 0x{:02x}", cmd),
+            MockError::BufferTooSmall => write!(f, "response buffer too small"),
+            MockError::NoResponse => write!(f, "tag didn't respond"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for MockError {}
+
+// NAK answering refused write (invalid operation)
+const NAK: u8 = 0x04;
+const ACK: u8 = 0x0A;
+
+// State of Gen1a backdoor
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Backdoor {
+    Locked,
+    // Received the first unlock command
+    Armed,
+    Unlocked,
+}
+
+/// In-memory Mifare Classic 4K tag for testing without hardware.
+///
+/// Interprets authentication, read, write, value block and HALT commands. Keys are taken from
+/// sector trailers stored in the image, so they can be changed by writing trailers as on real
+/// tags. Reading a block outside of authenticated sector fails with `NotAuthenticated`. As real
+/// tags, the tag doesn't answer authentication with wrong key (`NoResponse`) and doesn't
+/// respond after it or HALT until it's selected again using `NFCTag::select()`.
+/// Access conditions are enforced only for writes - writing of data block or sector trailer
+/// (which is written as a whole, only if access bits are writable) that they don't allow is
+/// answered with NAK 0x4, same as writing of block 0. When reading sector trailer, key A (and
+/// key B if it's not readable) reads back as zeros.
+///
+/// The tag can simulate magic Gen1a tag, see `set_gen1a()`.
+pub struct MockTag {
+    uid: Uid,
+    bcc: [u8; 2],
+    image: [u8; 4096],
+    authenticated: Option<SectorBlockOffset<Cap4K>>,
+    // Key used for authentication to `authenticated` sector
+    key_option: KeyOption,
+    value_register: Option<ValueBlock>,
+    present: bool,
+    // Set after failed authentication or HALT, the tag doesn't respond until selected again
+    halted: bool,
+    gen1a: bool,
+    backdoor: Backdoor,
+}
+
+impl MockTag {
+    /// Creates blank tag with all keys set to FFFFFFFFFFFF and transport access bits.
+    pub fn new(uid: Uid) -> Self {
+        let mut image = [0; 4096];
+        let uid_bytes = uid.as_bytes();
+        image[..uid_bytes.len()].copy_from_slice(uid_bytes);
+
+        let mut tag = MockTag {
+            uid: uid,
+            bcc: uid.bcc().0,
+            image: image,
+            authenticated: None,
+            key_option: KeyOption::KeyA,
+            value_register: None,
+            present: true,
+            halted: false,
+            gen1a: false,
+            backdoor: Backdoor::Locked,
+        };
+        for sector in 0..40 {
+            let trailer = tag.trailer_index(sector);
+            tag.image[trailer..(trailer + 6)].copy_from_slice(&[0xFF; 6]);
+            tag.image[(trailer + 6)..(trailer + 10)].copy_from_slice(&AccessBits::TRANSPORT.to_bytes());
+            tag.image[(trailer + 10)..(trailer + 16)].copy_from_slice(&[0xFF; 6]);
+        }
+        tag
+    }
+
+    /// Returns the image of the tag.
+    pub fn image(&self) -> &[u8; 4096] {
+        &self.image
+    }
+
+    /// Returns the image of the tag for modification.
+    pub fn image_mut(&mut self) -> &mut [u8; 4096] {
+        &mut self.image
+    }
+
+    /// Sets key A of the sector (0 - 39).
+    pub fn set_key_a<K: Into<Key>>(&mut self, sector: u8, key: K) {
+        let trailer = self.trailer_index(sector);
+        self.image[trailer..(trailer + 6)].copy_from_slice(key.into().as_bytes());
+    }
+
+    /// Sets key B of the sector (0 - 39).
+    pub fn set_key_b<K: Into<Key>>(&mut self, sector: u8, key: K) {
+        let trailer = self.trailer_index(sector);
+        self.image[(trailer + 10)..(trailer + 16)].copy_from_slice(key.into().as_bytes());
+    }
+
+    /// Sets BCC bytes reported by `NFCTag::bcc()`, e.g. to simulate corrupted anticollision.
+    ///
+    /// Only the first byte is used for single-size UID. By default, correct BCC is reported.
+    pub fn set_bcc(&mut self, bcc: [u8; 2]) {
+        self.bcc = bcc;
+    }
+
+    /// Makes the tag behave as magic Gen1a tag (or genuine tag, which is the default).
+    ///
+    /// Gen1a tag accepts backdoor commands sent using `NFCTag::transceive_bits()`. When
+    /// unlocked, all blocks (including block 0) can be read and written without
+    /// authentication.
+    pub fn set_gen1a(&mut self, gen1a: bool) {
+        self.gen1a = gen1a;
+    }
+
+    /// Simulates removing the tag from the field (or putting it back).
+    ///
+    /// Removing the tag resets authentication.
+    pub fn set_present(&mut self, present: bool) {
+        self.present = present;
+        if !present {
+            self.authenticated = None;
+            self.backdoor = Backdoor::Locked;
+            self.halted = false;
+        }
+    }
+
+    // Returns index of sector trailer in image
+    fn trailer_index(&self, sector: u8) -> usize {
+        let sector = crate::numerics::SectorNumber4K::new(sector).expect("invalid sector number");
+        let trailer: u8 = SectorBlockOffset::from(sector).sector_trailer().into();
+        trailer as usize * 16
+    }
+
+    // Checks that block is in authenticated sector and returns its index in image
+    fn check_block(&self, block: u8) -> Result<usize, MockError> {
+        let block_offset = AbsoluteBlockOffset::<Cap4K>::new(block).ok_or(MockError::InvalidCommand)?;
+        if self.backdoor == Backdoor::Unlocked {
+            return Ok(block as usize * 16);
+        }
+        if self.authenticated != Some(block_offset.sector_offset()) {
+            return Err(MockError::NotAuthenticated);
+        }
+        Ok(block as usize * 16)
+    }
+
+    fn authenticate(&mut self, data_to_tag: &[u8]) -> Result<usize, MockError> {
+        if data_to_tag.len() < 8 {
+            return Err(MockError::InvalidCommand);
+        }
+        self.authenticated = None;
+        self.backdoor = Backdoor::Locked;
+
+        let block = AbsoluteBlockOffset::<Cap4K>::new(data_to_tag[1]).ok_or(MockError::InvalidCommand)?;
+        let sector_offset = block.sector_offset();
+        let trailer = u8::from(sector_offset.sector_trailer()) as usize * 16;
+        let key = if data_to_tag[0] == 0x60 {
+            &self.image[trailer..(trailer + 6)]
+        } else {
+            &self.image[(trailer + 10)..(trailer + 16)]
+        };
+
+        if key == &data_to_tag[2..8] {
+            self.authenticated = Some(sector_offset);
+            self.key_option = if data_to_tag[0] == 0x60 { KeyOption::KeyA } else { KeyOption::KeyB };
+            Ok(0)
+        } else {
+            // Real tags don't answer authentication with wrong key
+            self.halted = true;
+            Err(MockError::NoResponse)
+        }
+    }
+
+    fn read(&mut self, block: u8, data_from_tag: &mut [u8]) -> Result<usize, MockError> {
+        let index = self.check_block(block)?;
+        if data_from_tag.len() < 16 {
+            return Err(MockError::BufferTooSmall);
+        }
+        data_from_tag[..16].copy_from_slice(&self.image[index..(index + 16)]);
+
+        let is_trailer = self.authenticated.map(|sector| u8::from(sector.sector_trailer())) == Some(block);
+        if is_trailer {
+            for byte in &mut data_from_tag[..6] {
+                *byte = 0;
+            }
+            let mut access_bytes = [0; 4];
+            access_bytes.copy_from_slice(&data_from_tag[6..10]);
+            let key_b_readable = AccessBits::from_bytes(&access_bytes).map(|bits| bits.trailer().key_b_readable()).unwrap_or(false);
+            if !key_b_readable {
+                for byte in &mut data_from_tag[10..16] {
+                    *byte = 0;
+                }
+            }
+        }
+        Ok(16)
+    }
+
+    fn write(&mut self, block: u8, data: &[u8], data_from_tag: &mut [u8]) -> Result<usize, MockError> {
+        let index = self.check_block(block)?;
+        if data.len() != 16 {
+            return Err(MockError::InvalidCommand);
+        }
+        if self.backdoor != Backdoor::Unlocked && !self.write_allowed(block) {
+            if data_from_tag.is_empty() {
+                return Err(MockError::BufferTooSmall);
+            }
+            data_from_tag[0] = NAK;
+            return Ok(1);
+        }
+        self.image[index..(index + 16)].copy_from_slice(data);
+        Ok(0)
+    }
+
+    // Checks access conditions of block in authenticated sector
+    fn write_allowed(&self, block: u8) -> bool {
+        // Manufacturer block is read-only, unless backdoor is used
+        let sector_offset = match self.authenticated {
+            Some(sector_offset) if block != 0 => sector_offset,
+            _ => return false,
+        };
+        let trailer = u8::from(sector_offset.sector_trailer());
+        let index = trailer as usize * 16;
+        let mut access_bytes = [0; 4];
+        access_bytes.copy_from_slice(&self.image[(index + 6)..(index + 10)]);
+        let access_bits = match AccessBits::from_bytes(&access_bytes) {
+            Ok(access_bits) => access_bits,
+            Err(_) => return false,
+        };
+
+        if block == trailer {
+            return access_bits.trailer().access_bits_writable(self.key_option);
+        }
+        let offset = block - u8::from(sector_offset);
+        // Large sectors have groups of five blocks
+        let group = if sector_offset.block_count() == 4 { offset } else { offset / 5 };
+        access_bits.data_writable(group as usize, self.key_option)
+    }
+
+    fn value_operation(&mut self, cmd: u8, block: u8, operand: &[u8]) -> Result<usize, MockError> {
+        let index = self.check_block(block)?;
+        if operand.len() != 4 {
+            return Err(MockError::InvalidCommand);
+        }
+        let mut buf = [0; 16];
+        buf.copy_from_slice(&self.image[index..(index + 16)]);
+        let value_block = ValueBlock::from_bytes(&buf).map_err(|_| MockError::InvalidCommand)?;
+
+        let operand = (operand[0] as u32 | (operand[1] as u32) << 8 | (operand[2] as u32) << 16 | (operand[3] as u32) << 24) as i32;
+        let value = match cmd {
+            0xC1 => value_block.value().wrapping_add(operand),
+            0xC0 => value_block.value().wrapping_sub(operand),
+            _ => value_block.value(),
+        };
+        self.value_register = Some(ValueBlock::new(value, value_block.address()));
+        Ok(0)
+    }
+
+    fn transfer(&mut self, block: u8) -> Result<usize, MockError> {
+        let index = self.check_block(block)?;
+        let value_block = self.value_register.take().ok_or(MockError::InvalidCommand)?;
+        self.image[index..(index + 16)].copy_from_slice(&value_block.to_bytes());
+        Ok(0)
+    }
+}
+
+impl NFCTag for MockTag {
+    type TransceiveError = MockError;
+
+    fn tag_id(&self) -> &[u8] {
+        self.uid.as_bytes()
+    }
+
+    fn atqa(&self) -> Option<u16> {
+        match self.uid {
+            Uid::Single(_) => Some(0x0002),
+            Uid::Double(_) => Some(0x0042),
+        }
+    }
+
+    fn sak(&self) -> Option<u8> {
+        Some(0x18)
+    }
+
+    fn bcc(&self) -> Option<&[u8]> {
+        Some(&self.bcc[..self.uid.bcc().1])
+    }
+
+    fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Self::TransceiveError> {
+        if !self.present {
+            return Err(MockError::NotPresent);
+        }
+        if self.halted {
+            return Err(MockError::NoResponse);
+        }
+        if data_to_tag.len() < 2 {
+            return Err(MockError::InvalidCommand);
+        }
+
+        match data_to_tag[0] {
+            0x60 | 0x61 => self.authenticate(data_to_tag),
+            0x30 => self.read(data_to_tag[1], data_from_tag),
+            0xA0 => self.write(data_to_tag[1], &data_to_tag[2..], data_from_tag),
+            cmd @ 0xC0 ..= 0xC2 => self.value_operation(cmd, data_to_tag[1], &data_to_tag[2..]),
+            0xB0 => self.transfer(data_to_tag[1]),
+            // Same as real tag, HALT isn't answered
+            0x50 => {
+                self.authenticated = None;
+                self.backdoor = Backdoor::Locked;
+                self.halted = true;
+                Err(MockError::NoResponse)
+            },
+            cmd => Err(MockError::UnsupportedCommand(cmd)),
+        }
+    }
+
+    fn transceive_bits(&mut self, data_to_tag: &[u8], last_byte_bits: u8, data_from_tag: &mut [u8]) -> Option<Result<usize, Self::TransceiveError>> {
+        if !self.present {
+            return Some(Err(MockError::NotPresent));
+        }
+        if data_from_tag.is_empty() {
+            return Some(Err(MockError::BufferTooSmall));
+        }
+
+        self.backdoor = match (data_to_tag, last_byte_bits, self.backdoor) {
+            (&[0x40], 7, _) if self.gen1a => Backdoor::Armed,
+            (&[0x43], 8, Backdoor::Armed) => {
+                self.halted = false;
+                Backdoor::Unlocked
+            },
+            _ => {
+                self.backdoor = Backdoor::Locked;
+                return Some(Err(MockError::NoResponse));
+            },
+        };
+        data_from_tag[0] = ACK;
+        Some(Ok(1))
+    }
+
+    fn check_presence(&mut self) -> Option<bool> {
+        Some(self.present)
+    }
+
+    fn is_timeout(&self, error: &Self::TransceiveError) -> Option<bool> {
+        Some(*error == MockError::NoResponse)
+    }
+
+    fn select(&mut self) -> Option<Result<(), Self::TransceiveError>> {
+        if !self.present {
+            return Some(Err(MockError::NotPresent));
+        }
+        self.authenticated = None;
+        self.backdoor = Backdoor::Locked;
+        self.halted = false;
+        Some(Ok(()))
+    }
+}
+
+#[cfg(feature = "async")]
+impl crate::AsyncNFCTag for MockTag {
+    type TransceiveError = MockError;
+
+    fn tag_id(&self) -> &[u8] {
+        self.uid.as_bytes()
+    }
+
+    async fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Self::TransceiveError> {
+        NFCTag::transceive(self, data_to_tag, data_from_tag)
+    }
+}
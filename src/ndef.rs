@@ -0,0 +1,159 @@
+use core::fmt;
+use std::vec::Vec;
+use crate::{NFCTag, MifareTag, Key, KeyOption, DumpError};
+use crate::mad::NDEF_AID;
+use crate::numerics::{TagCapacity, SectorNumber};
+
+/// Key A of NDEF sectors.
+pub const NDEF_KEY: Key = Key::new([0xD3, 0xF7, 0xD3, 0xF7, 0xD3, 0xF7]);
+
+// Types of TLV blocks
+const TLV_NULL: u8 = 0x00;
+const TLV_NDEF_MESSAGE: u8 = 0x03;
+const TLV_TERMINATOR: u8 = 0xFE;
+
+/// Error returned by `MifareTag::read_ndef()`.
+#[derive(Debug)]
+pub enum NdefError<E> {
+    /// Reading of MAD or NDEF sectors failed (this includes invalid MAD).
+    Read(DumpError<E>),
+    /// MAD doesn't assign any sector to NDEF.
+    NotFormatted,
+    /// NDEF sectors don't contain NDEF message TLV.
+    NoMessage,
+    /// Length of TLV exceeds data stored in NDEF sectors.
+    Truncated,
+}
+
+impl<E: fmt::Display> fmt::Display for NdefError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NdefError::Read(ref error) => write!(f, "reading of NDEF failed: {}", error),
+            NdefError::NotFormatted => write!(f, "MAD doesn't contain any NDEF sector"),
+            NdefError::NoMessage => write!(f, "NDEF sectors don't contain NDEF message"),
+            NdefError::Truncated => write!(f, "NDEF message is truncated"),
+        }
+    }
+}
+
+impl<E: ::std::error::Error + 'static> ::std::error::Error for NdefError<E> {
+    fn source(&self) -> Option<&(dyn ::std::error::Error + 'static)> {
+        match *self {
+            NdefError::Read(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// Finds value of the first NDEF message TLV, stopping at terminator TLV
+fn find_message<E>(data: &[u8]) -> Result<&[u8], NdefError<E>> {
+    let mut pos = 0;
+    while pos < data.len() {
+        let tlv_type = data[pos];
+        pos += 1;
+        match tlv_type {
+            TLV_NULL => continue,
+            TLV_TERMINATOR => break,
+            _ => (),
+        }
+
+        // Length has one byte, or three if the first one is 0xFF
+        let mut len = *data.get(pos).ok_or(NdefError::Truncated)? as usize;
+        pos += 1;
+        if len == 0xFF {
+            if pos + 2 > data.len() {
+                return Err(NdefError::Truncated);
+            }
+            len = (data[pos] as usize) << 8 | data[pos + 1] as usize;
+            pos += 2;
+        }
+        if pos + len > data.len() {
+            return Err(NdefError::Truncated);
+        }
+
+        if tlv_type == TLV_NDEF_MESSAGE {
+            return Ok(&data[pos..(pos + len)]);
+        }
+        pos += len;
+    }
+    Err(NdefError::NoMessage)
+}
+
+impl<T: NFCTag, Cap: TagCapacity> MifareTag<T, Cap> {
+    /// Reads NDEF message from tag formatted according to NFC Forum mapping of Mifare Classic.
+    ///
+    /// Finds NDEF sectors using MAD (see `read_mad()`), reads them using `NDEF_KEY` as key A
+    /// and returns the raw NDEF message (records aren't parsed).
+    pub fn read_ndef(&mut self) -> Result<Vec<u8>, NdefError<T::TransceiveError>> {
+        let mad = self.read_mad().map_err(NdefError::Read)?;
+
+        let mut data = Vec::new();
+        let mut sector_data = [0; 256];
+        // Skips sectors that the tag doesn't have (if MAD is damaged or Cap is wrong)
+        for sector in mad.sectors(NDEF_AID).filter_map(|sector| SectorNumber::<Cap>::new(sector.into())) {
+            let data_len = (sector.block_count() as usize - 1) * 16;
+            self.dump_sector(sector, KeyOption::KeyA, &NDEF_KEY, &mut sector_data).map_err(NdefError::Read)?;
+            data.extend_from_slice(&sector_data[..data_len]);
+        }
+        if data.is_empty() {
+            return Err(NdefError::NotFormatted);
+        }
+
+        find_message(&data).map(|message| message.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(data: &[u8]) -> Result<&[u8], NdefError<()>> {
+        find_message(data)
+    }
+
+    #[test]
+    fn short_length() {
+        assert_eq!(find(&[0x03, 0x03, 0xD0, 0x00, 0x00, 0xFE]).unwrap(), [0xD0, 0x00, 0x00]);
+        assert_eq!(find(&[0x03, 0x00, 0xFE]).unwrap(), []);
+    }
+
+    #[test]
+    fn long_length() {
+        let mut data = vec![0x03, 0xFF, 0x01, 0x02];
+        data.extend((0..258).map(|i| i as u8));
+        data.push(0xFE);
+        let message = find(&data).unwrap();
+        assert_eq!(message.len(), 258);
+        assert_eq!(message[257], 0x01);
+    }
+
+    #[test]
+    fn skips_null_and_other_tlvs() {
+        // NULL TLVs and Lock Control TLV precede the message
+        let data = [0x00, 0x00, 0x01, 0x03, 0xA0, 0x10, 0x44, 0x00, 0x03, 0x01, 0xAA, 0xFE];
+        assert_eq!(find(&data).unwrap(), [0xAA]);
+    }
+
+    #[test]
+    fn stops_at_terminator() {
+        match find(&[0x00, 0xFE, 0x03, 0x01, 0xAA]) {
+            Err(NdefError::NoMessage) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match find(&[0x00; 16]) {
+            Err(NdefError::NoMessage) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let truncated: [&[u8]; 4] = [&[0x03], &[0x03, 0x05, 0x01, 0x02], &[0x03, 0xFF, 0x01], &[0x03, 0xFF, 0x00, 0x02, 0x01]];
+        for data in &truncated {
+            match find(data) {
+                Err(NdefError::Truncated) => (),
+                result => panic!("unexpected result of {:02x?}: {:?}", data, result),
+            }
+        }
+    }
+}
@@ -1,4 +1,49 @@
-use ::std::marker::PhantomData;
+use ::core::marker::PhantomData;
+use ::core::convert::TryFrom;
+use ::core::fmt;
+
+// Sectors 32 and above (present only on 4K tags) have 16 blocks instead of 4.
+const FIRST_LARGE_SECTOR: u8 = 32;
+const FIRST_LARGE_SECTOR_BLOCK: u8 = 128;
+
+// Returns block offset of the first block in sector
+fn sector_to_block(sector_number: u8) -> u8 {
+    if sector_number < FIRST_LARGE_SECTOR {
+        sector_number * 4
+    } else {
+        FIRST_LARGE_SECTOR_BLOCK + (sector_number - FIRST_LARGE_SECTOR) * 16
+    }
+}
+
+// Returns number of sector containing the block
+fn block_to_sector(block_offset: u8) -> u8 {
+    if block_offset < FIRST_LARGE_SECTOR_BLOCK {
+        block_offset / 4
+    } else {
+        FIRST_LARGE_SECTOR + (block_offset - FIRST_LARGE_SECTOR_BLOCK) / 16
+    }
+}
+
+/// Error returned when converting invalid number to one of the numeric types.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum NumericError {
+    /// The number is greater than `max`, the greatest valid value.
+    OutOfRange { got: u8, max: u8 },
+    /// The block offset doesn't point to the first block of a sector.
+    NotSectorAligned { got: u8 },
+}
+
+impl fmt::Display for NumericError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NumericError::OutOfRange { got, max } => write!(f, "{} is out of range (maximum is {})", got, max),
+            NumericError::NotSectorAligned { got } => write!(f, "block {} isn't the first block of a sector", got),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for NumericError {}
 
 /// Represents capacity of a tag
 pub trait TagCapacity {
@@ -8,8 +53,30 @@ pub trait TagCapacity {
         (Self::bytes() / 64) as u8
     }
 
-    fn max_blocks() -> u8 {
-        (Self::bytes() / 16) as u8
+    /// Number of blocks. This is u16 because 4K tag has 256 blocks.
+    fn max_blocks() -> u16 {
+        Self::bytes() / 16
+    }
+
+    /// Number of blocks usable for data - all blocks except sector trailers and the
+    /// manufacturer block (block 0).
+    fn data_blocks() -> u16 {
+        Self::max_blocks() - Self::max_sectors() as u16 - 1
+    }
+
+    /// Number of bytes usable for data, see `data_blocks()`.
+    fn data_bytes() -> u16 {
+        Self::data_blocks() * 16
+    }
+}
+
+/// Tag capacity of Mifare Classic Mini (320 bytes, 5 sectors).
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub struct CapMini;
+
+impl TagCapacity for CapMini {
+    fn bytes() -> u16 {
+        320
     }
 }
 
@@ -23,6 +90,16 @@ impl TagCapacity for Cap1K {
     }
 }
 
+/// Tag capacity of 2KiB.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Cap2K;
+
+impl TagCapacity for Cap2K {
+    fn bytes() -> u16 {
+        2048
+    }
+}
+
 /// Tag capacity of 4KiB.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Cap4K;
@@ -31,19 +108,45 @@ impl TagCapacity for Cap4K {
     fn bytes() -> u16 {
         4096
     }
+
+    /// 32 sectors of 4 blocks and 8 sectors of 16 blocks.
+    fn max_sectors() -> u8 {
+        40
+    }
 }
 
 /// Represents valid sector number within 1K Mifare tag.
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub struct SectorNumber<Cap> (u8, PhantomData<Cap>);
 
+// Derive would require Cap: Copy
+impl<Cap> Clone for SectorNumber<Cap> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Cap> Copy for SectorNumber<Cap> {}
+
 impl<Cap: TagCapacity> SectorNumber<Cap> {
     /// Creates SectorNumber while checking for validity.
+    ///
+    /// Use `TryFrom` to find out why the number is invalid.
     pub fn new(sector_number: u8) -> Option<Self> {
-        if sector_number < Cap::max_sectors() {
-            Some(SectorNumber::raw(sector_number))
+        SectorNumber::try_from(sector_number).ok()
+    }
+
+    /// Returns iterator over all sectors of tag with given capacity.
+    pub fn all() -> Sectors<Cap> {
+        Sectors { next: 0, _phantom: Default::default() }
+    }
+
+    /// Returns number of blocks in the sector, including the trailer.
+    pub fn block_count(self) -> u8 {
+        if self.0 < FIRST_LARGE_SECTOR {
+            4
         } else {
-            None
+            16
         }
     }
 
@@ -53,36 +156,104 @@ impl<Cap: TagCapacity> SectorNumber<Cap> {
     }
 }
 
+/// Iterator over all sectors of a tag, created by `SectorNumber::all()`.
+#[derive(Debug, Clone)]
+pub struct Sectors<Cap> {
+    next: u8,
+    _phantom: PhantomData<Cap>,
+}
+
+impl<Cap: TagCapacity> Iterator for Sectors<Cap> {
+    type Item = SectorNumber<Cap>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sector = SectorNumber::new(self.next);
+        if sector.is_some() {
+            self.next += 1;
+        }
+        sector
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (Cap::max_sectors() - self.next) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<Cap: TagCapacity> ExactSizeIterator for Sectors<Cap> {}
+
+impl<Cap: TagCapacity> TryFrom<u8> for SectorNumber<Cap> {
+    type Error = NumericError;
+
+    fn try_from(sector_number: u8) -> Result<Self, Self::Error> {
+        if sector_number < Cap::max_sectors() {
+            Ok(SectorNumber::raw(sector_number))
+        } else {
+            Err(NumericError::OutOfRange { got: sector_number, max: Cap::max_sectors() - 1 })
+        }
+    }
+}
+
 impl<Cap: TagCapacity> From<SectorNumber<Cap>> for u8 {
     fn from(sector_number: SectorNumber<Cap>) -> Self {
         sector_number.0
     }
 }
 
+/// Abbreviation
+pub type SectorNumberMini = SectorNumber<CapMini>;
+
 /// Abbreviation
 pub type SectorNumber1K = SectorNumber<Cap1K>;
 
+/// Abbreviation
+pub type SectorNumber2K = SectorNumber<Cap2K>;
+
 /// Abbreviation
 pub type SectorNumber4K = SectorNumber<Cap4K>;
 
-/// A tag with lower capacity can be safely treated as a tag with greater capacity.
-impl From<SectorNumber1K> for SectorNumber4K {
-    fn from(sector_number: SectorNumber1K) -> Self {
-        SectorNumber::raw(sector_number.0)
+macro_rules! impl_widening {
+    ($from:ty => $($to:ty),*) => {
+        $(
+            /// A tag with lower capacity can be safely treated as a tag with greater capacity.
+            impl From<SectorNumber<$from>> for SectorNumber<$to> {
+                fn from(sector_number: SectorNumber<$from>) -> Self {
+                    SectorNumber::raw(sector_number.0)
+                }
+            }
+        )*
     }
 }
 
-/// Offset within sector.
+impl_widening!(CapMini => Cap1K, Cap2K, Cap4K);
+impl_widening!(Cap1K => Cap2K, Cap4K);
+impl_widening!(Cap2K => Cap4K);
+
+/// Offset of data block within sector.
+///
+/// Offsets 0 - 2 are data blocks in all sectors, offsets 3 - 14 only in large (16 block)
+/// sectors of 4K tags. In other sectors, offset 3 is the sector trailer, so operations on
+/// data blocks refuse it (see `SectorBlockOffset::checked_add()`).
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
 pub struct BlockOffset (u8);
 
 impl BlockOffset {
     /// Creates BlockOffset while checking for validity.
+    ///
+    /// Use `TryFrom` to find out why the offset is invalid.
     pub fn new(block_offset: u8) -> Option<Self> {
-        if block_offset < 3 {
-            Some(BlockOffset(block_offset))
+        BlockOffset::try_from(block_offset).ok()
+    }
+}
+
+impl TryFrom<u8> for BlockOffset {
+    type Error = NumericError;
+
+    fn try_from(block_offset: u8) -> Result<Self, Self::Error> {
+        if block_offset < 15 {
+            Ok(BlockOffset(block_offset))
         } else {
-            None
+            Err(NumericError::OutOfRange { got: block_offset, max: 14 })
         }
     }
 }
@@ -94,27 +265,39 @@ impl From<BlockOffset> for u8 {
 }
 
 /// Represents absolute Mifare tag address in blocks.
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub struct AbsoluteBlockOffset<Cap> (u8, PhantomData<Cap>);
 
+impl<Cap> Clone for AbsoluteBlockOffset<Cap> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Cap> Copy for AbsoluteBlockOffset<Cap> {}
+
 impl<Cap: TagCapacity> AbsoluteBlockOffset<Cap> {
     /// Creates AbsoluteBlockOffset while checking for validity.
+    ///
+    /// Use `TryFrom` to find out why the offset is invalid.
     pub fn new(block_offset: u8) -> Option<Self> {
-        if block_offset < Cap::max_blocks() {
-            Some(AbsoluteBlockOffset::raw(block_offset))
-        } else {
-            None
-        }
+        AbsoluteBlockOffset::try_from(block_offset).ok()
     }
 
     /// Returns the position of sector start.
     pub fn sector_offset(self) -> SectorBlockOffset<Cap> {
-        SectorBlockOffset::raw(self.0 - self.0 % 4)
+        SectorBlockOffset::raw(sector_to_block(block_to_sector(self.0)))
     }
 
     /// Returns offset from beginning of the sector.
-    pub fn block_within_sector(self) -> BlockOffset {
-        BlockOffset(self.0 % 4)
+    ///
+    /// Returns `None` if the block is sector trailer.
+    pub fn block_within_sector(self) -> Option<BlockOffset> {
+        if self.0 == self.sector_offset().sector_trailer().0 {
+            None
+        } else {
+            Some(BlockOffset(self.0 - sector_to_block(block_to_sector(self.0))))
+        }
     }
 
     fn raw(val: u8) -> Self {
@@ -122,6 +305,18 @@ impl<Cap: TagCapacity> AbsoluteBlockOffset<Cap> {
     }
 }
 
+impl<Cap: TagCapacity> TryFrom<u8> for AbsoluteBlockOffset<Cap> {
+    type Error = NumericError;
+
+    fn try_from(block_offset: u8) -> Result<Self, Self::Error> {
+        if (block_offset as u16) < Cap::max_blocks() {
+            Ok(AbsoluteBlockOffset::raw(block_offset))
+        } else {
+            Err(NumericError::OutOfRange { got: block_offset, max: (Cap::max_blocks() - 1) as u8 })
+        }
+    }
+}
+
 impl<Cap: TagCapacity> From<AbsoluteBlockOffset<Cap>> for u8 {
     fn from(block_offset: AbsoluteBlockOffset<Cap>) -> Self {
         block_offset.0
@@ -132,20 +327,46 @@ impl<Cap: TagCapacity> From<AbsoluteBlockOffset<Cap>> for u8 {
 ///
 /// Almost same as SectorNumber. The difference it that this one stores pre-calculated
 /// offset to avoid re-calculating each time.
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub struct SectorBlockOffset<Cap> (u8, PhantomData<Cap>);
 
+impl<Cap> Clone for SectorBlockOffset<Cap> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Cap> Copy for SectorBlockOffset<Cap> {}
+
 impl<Cap: TagCapacity> SectorBlockOffset<Cap> {
+    /// Creates SectorBlockOffset while checking that it points to the first block of a sector.
+    ///
+    /// Use `TryFrom` to find out why the offset is invalid.
     pub fn new(block_offset: u8) -> Option<Self> {
-        if block_offset < Cap::max_blocks() && block_offset % 4 == 0 {
-            Some(SectorBlockOffset::raw(block_offset))
+        SectorBlockOffset::try_from(block_offset).ok()
+    }
+
+    /// Returns number of blocks in the sector, including the trailer.
+    pub fn block_count(self) -> u8 {
+        SectorNumber::<Cap>::from(self).block_count()
+    }
+
+    /// Adds offset within the sector, checking that the result is data block of this sector.
+    ///
+    /// Returns `None` if `offset` points to the sector trailer or past it (offsets above 2
+    /// are valid only in large sectors) or the result is past the end of the tag. Use
+    /// `sector_trailer()` to get position of the trailer.
+    pub fn checked_add(self, offset: BlockOffset) -> Option<AbsoluteBlockOffset<Cap>> {
+        if offset.0 < self.block_count() - 1 {
+            AbsoluteBlockOffset::new(self.0 + offset.0)
         } else {
             None
         }
     }
 
+    /// Returns the position of sector trailer, which is the last block of the sector.
     pub fn sector_trailer(self) -> AbsoluteBlockOffset<Cap> {
-        AbsoluteBlockOffset::raw(self.0 + 3)
+        AbsoluteBlockOffset::raw(self.0 + (self.block_count() - 1))
     }
 
     fn raw(val: u8) -> Self {
@@ -153,6 +374,19 @@ impl<Cap: TagCapacity> SectorBlockOffset<Cap> {
     }
 }
 
+impl<Cap: TagCapacity> TryFrom<u8> for SectorBlockOffset<Cap> {
+    type Error = NumericError;
+
+    fn try_from(block_offset: u8) -> Result<Self, Self::Error> {
+        let absolute = AbsoluteBlockOffset::<Cap>::try_from(block_offset)?;
+        if u8::from(absolute.sector_offset()) == block_offset {
+            Ok(SectorBlockOffset::raw(block_offset))
+        } else {
+            Err(NumericError::NotSectorAligned { got: block_offset })
+        }
+    }
+}
+
 impl<Cap: TagCapacity> From<SectorBlockOffset<Cap>> for u8 {
     fn from(block_offset: SectorBlockOffset<Cap>) -> Self {
         block_offset.0
@@ -161,14 +395,16 @@ impl<Cap: TagCapacity> From<SectorBlockOffset<Cap>> for u8 {
 
 impl<Cap: TagCapacity> From<SectorBlockOffset<Cap>> for SectorNumber<Cap> {
     fn from(block_offset: SectorBlockOffset<Cap>) -> Self {
-        SectorNumber::raw(block_offset.0 / 4)
+        SectorNumber::raw(block_to_sector(block_offset.0))
     }
 }
 
-impl<Cap: TagCapacity> ::std::ops::Add<BlockOffset> for SectorBlockOffset<Cap> {
+/// Unchecked addition, use `checked_add()` if `offset` may not be data block of the sector.
+impl<Cap: TagCapacity> ::core::ops::Add<BlockOffset> for SectorBlockOffset<Cap> {
     type Output = AbsoluteBlockOffset<Cap>;
 
     fn add(self, offset: BlockOffset) -> Self::Output {
+        debug_assert!(offset.0 < self.block_count() - 1, "block offset {} isn't data block of sector", offset.0);
         let lhs: u8 = self.into();
         let rhs: u8 = offset.into();
         AbsoluteBlockOffset::raw(lhs + rhs)
@@ -181,6 +417,133 @@ where CapF: TagCapacity,
       SectorNumber<CapF>: Into<SectorNumber<CapT>> {
 
     fn from(sector_number: SectorNumber<CapF>) -> Self {
-        sector_number.into().into()
+        let sector_number: SectorNumber<CapT> = sector_number.into();
+        SectorBlockOffset::raw(sector_to_block(sector_number.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sector_count_4k() {
+        assert!(SectorNumber4K::new(39).is_some());
+        assert!(SectorNumber4K::new(40).is_none());
+        assert_eq!(Cap4K::max_blocks(), 256);
+    }
+
+    #[test]
+    fn sectors_of_4k_tag() {
+        let sectors = SectorNumber4K::all();
+        assert_eq!(sectors.len(), 40);
+        assert_eq!(sectors.count(), 40);
+
+        for sector in SectorNumber4K::all() {
+            let expected = if u8::from(sector) < 32 { 4 } else { 16 };
+            assert_eq!(sector.block_count(), expected);
+            assert_eq!(SectorBlockOffset::<Cap4K>::from(sector).block_count(), expected);
+        }
+    }
+
+    #[test]
+    fn sectors_of_1k_tag() {
+        assert_eq!(SectorNumber1K::all().count(), 16);
+        assert!(SectorNumber1K::all().all(|sector| sector.block_count() == 4));
+    }
+
+    #[test]
+    fn misaligned_sector_block_offset() {
+        assert_eq!(SectorBlockOffset::<Cap1K>::try_from(6).map(u8::from), Err(NumericError::NotSectorAligned { got: 6 }));
+        assert_eq!(SectorBlockOffset::<Cap4K>::try_from(127).map(u8::from), Err(NumericError::NotSectorAligned { got: 127 }));
+        // Multiple of 4, but in the middle of large sector
+        assert_eq!(SectorBlockOffset::<Cap4K>::try_from(132).map(u8::from), Err(NumericError::NotSectorAligned { got: 132 }));
+        assert_eq!(SectorBlockOffset::<Cap4K>::try_from(144).map(u8::from), Ok(144));
+        assert!(SectorBlockOffset::<Cap1K>::new(6).is_none());
+    }
+
+    #[test]
+    fn out_of_range_values() {
+        assert_eq!(SectorNumber1K::try_from(16).map(u8::from), Err(NumericError::OutOfRange { got: 16, max: 15 }));
+        assert_eq!(SectorNumber4K::try_from(40).map(u8::from), Err(NumericError::OutOfRange { got: 40, max: 39 }));
+        assert_eq!(BlockOffset::try_from(15), Err(NumericError::OutOfRange { got: 15, max: 14 }));
+        assert_eq!(AbsoluteBlockOffset::<Cap1K>::try_from(64).map(u8::from), Err(NumericError::OutOfRange { got: 64, max: 63 }));
+        assert_eq!(AbsoluteBlockOffset::<CapMini>::try_from(20).map(u8::from), Err(NumericError::OutOfRange { got: 20, max: 19 }));
+        // Out of range offset is reported as such even if it's not aligned
+        assert_eq!(SectorBlockOffset::<Cap1K>::try_from(65).map(u8::from), Err(NumericError::OutOfRange { got: 65, max: 63 }));
+    }
+
+    #[test]
+    fn data_capacity() {
+        assert_eq!(Cap1K::data_blocks(), 47);
+        assert_eq!(Cap1K::data_bytes(), 752);
+        // 256 blocks - 40 trailers - block 0
+        assert_eq!(Cap4K::data_blocks(), 215);
+        assert_eq!(Cap4K::data_bytes(), 3440);
+    }
+
+    fn block(block: u8) -> AbsoluteBlockOffset<Cap4K> {
+        AbsoluteBlockOffset::new(block).unwrap()
+    }
+
+    #[test]
+    fn last_small_sector() {
+        let sector = SectorBlockOffset::<Cap4K>::from(SectorNumber4K::new(31).unwrap());
+        assert_eq!(u8::from(sector), 124);
+        assert_eq!(sector.block_count(), 4);
+        assert_eq!(u8::from(sector.sector_trailer()), 127);
+
+        assert_eq!(u8::from(block(126).sector_offset()), 124);
+        assert_eq!(block(126).block_within_sector(), BlockOffset::new(2));
+        assert_eq!(u8::from(block(127).sector_offset()), 124);
+        assert_eq!(block(127).block_within_sector(), None);
+    }
+
+    #[test]
+    fn first_large_sector() {
+        let sector = SectorBlockOffset::<Cap4K>::from(SectorNumber4K::new(32).unwrap());
+        assert_eq!(u8::from(sector), 128);
+        assert_eq!(sector.block_count(), 16);
+        assert_eq!(u8::from(sector.sector_trailer()), 143);
+        assert_eq!(u8::from(SectorNumber4K::from(sector)), 32);
+
+        assert_eq!(u8::from(block(128).sector_offset()), 128);
+        assert_eq!(block(128).block_within_sector(), BlockOffset::new(0));
+        assert_eq!(u8::from(block(142).sector_offset()), 128);
+        assert_eq!(block(142).block_within_sector(), BlockOffset::new(14));
+        assert_eq!(block(143).block_within_sector(), None);
+
+        assert_eq!(u8::from(block(144).sector_offset()), 144);
+        assert_eq!(u8::from(SectorNumber4K::from(block(144).sector_offset())), 33);
+    }
+
+    #[test]
+    fn last_large_sector() {
+        let sector = SectorBlockOffset::<Cap4K>::from(SectorNumber4K::new(39).unwrap());
+        assert_eq!(u8::from(sector), 240);
+        assert_eq!(u8::from(sector.sector_trailer()), 255);
+        assert_eq!(u8::from(block(255).sector_offset()), 240);
+        assert_eq!(block(255).block_within_sector(), None);
+    }
+
+    #[test]
+    fn block_offsets_of_large_sector() {
+        assert!(BlockOffset::new(14).is_some());
+        assert!(BlockOffset::new(15).is_none());
+
+        let sector = SectorBlockOffset::<Cap4K>::new(128).unwrap();
+        assert_eq!(sector.checked_add(BlockOffset::new(14).unwrap()).map(u8::from), Some(142));
+    }
+
+    #[test]
+    fn checked_add_refuses_trailer() {
+        let small = SectorBlockOffset::<Cap4K>::new(124).unwrap();
+        assert_eq!(small.checked_add(BlockOffset::new(2).unwrap()).map(u8::from), Some(126));
+        assert!(small.checked_add(BlockOffset::new(3).unwrap()).is_none());
+        assert!(small.checked_add(BlockOffset::new(4).unwrap()).is_none());
+
+        let large = SectorBlockOffset::<Cap4K>::new(240).unwrap();
+        assert_eq!(large.checked_add(BlockOffset::new(14).unwrap()).map(u8::from), Some(254));
+        assert!(SectorBlockOffset::<Cap1K>::new(60).unwrap().checked_add(BlockOffset::new(3).unwrap()).is_none());
     }
 }
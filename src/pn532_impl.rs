@@ -1,9 +1,13 @@
-use ::pn532::tags::{Tag, ISO14443A};
-use ::pn532::bus::{WaitRead, BusWrite};
-use ::pn532::PN532;
-use ::pn532::error::CommError;
-use ::NFCTag;
+use pn532::tags::{Tag, ISO14443A};
+use pn532::bus::{WaitRead, BusWrite};
+use pn532::PN532;
+use pn532::error::CommError;
+use crate::NFCTag;
 
+// select() keeps the default implementation returning None. PN532 selects the tag while
+// listing it (InListPassiveTarget), which creates new `Tag`, so it can't be selected again
+// through this impl. After a failed authentication, list the tag again and create new
+// `MifareTag`.
 impl<'r, 'p, D: WaitRead + BusWrite> NFCTag for Tag<'p, 'r, ISO14443A<'r>, PN532<D>> where CommError<D::ReadError, D::WriteError>: ::std::error::Error {
     type TransceiveError = CommError<D::ReadError, D::WriteError>;
 
@@ -11,6 +15,14 @@ impl<'r, 'p, D: WaitRead + BusWrite> NFCTag for Tag<'p, 'r, ISO14443A<'r>, PN532
         self.id()
     }
 
+    fn atqa(&self) -> Option<u16> {
+        Some(self.sens_res())
+    }
+
+    fn sak(&self) -> Option<u8> {
+        Some(self.sel_res())
+    }
+
     fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Self::TransceiveError> {
         (self as &mut Tag<ISO14443A, PN532<D>>).transceive(data_to_tag, data_from_tag)
     }
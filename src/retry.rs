@@ -0,0 +1,306 @@
+#[cfg(feature = "std")]
+use std::time::Duration;
+use crate::{NFCTag, KeyOption};
+
+/// Configures how many times `RetryTag` attempts to send a command.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    write_retries: bool,
+    #[cfg(feature = "std")]
+    backoff: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// Creates policy allowing `max_attempts` attempts (including the first one) without
+    /// waiting between them. Zero is treated as one. Only reading and authentication are
+    /// retried, see `with_write_retries()`.
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts: ::core::cmp::max(max_attempts, 1),
+            write_retries: false,
+            #[cfg(feature = "std")]
+            backoff: None,
+        }
+    }
+
+    /// Sets time to sleep before each retry.
+    #[cfg(feature = "std")]
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = Some(backoff);
+        self
+    }
+
+    /// Enables retrying of write, value (increment, decrement, restore) and transfer commands.
+    ///
+    /// WARNING: If only the acknowledgement was lost, the command is applied twice - e.g.
+    /// decrement followed by transfer would decrement the value twice. Besides, the tag
+    /// usually drops authentication after a lost frame, so the retry fails anyway.
+    pub fn with_write_retries(mut self) -> Self {
+        self.write_retries = true;
+        self
+    }
+
+    /// Returns true if write, value and transfer commands are retried.
+    pub fn write_retries(&self) -> bool {
+        self.write_retries
+    }
+
+    /// Returns maximum number of attempts.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns time to sleep before each retry.
+    #[cfg(feature = "std")]
+    pub fn backoff(&self) -> Option<Duration> {
+        self.backoff
+    }
+}
+
+/// Function selecting the tag again, used by `RetryTag::new()`.
+pub type DefaultReselect<T> = fn(&mut T) -> Option<Result<(), <T as NFCTag>::TransceiveError>>;
+
+/// Wrapper of `NFCTag` which retries failed `transceive()` calls.
+///
+/// Only calls failing with error are retried - responses (including NAK) are returned as they
+/// are. By default, only read and authentication commands are retried, because they can be
+/// safely repeated. Write, value and transfer commands are retried only if enabled by
+/// `RetryPolicy::with_write_retries()`, other commands (including HALT) never.
+/// Tags stop responding after failed authentication, so `reselect` is called before
+/// retrying authentication commands. If it fails, its error is returned. If it returns `None`
+/// (the tag can't be selected again), authentication isn't retried. `select()` of this tag
+/// calls `reselect` too.
+///
+/// Note that backends which report rejected key as communication error make each
+/// authentication with wrong key take `max_attempts` attempts.
+pub struct RetryTag<T, F> {
+    tag: T,
+    policy: RetryPolicy,
+    reselect: F,
+}
+
+impl<T: NFCTag> RetryTag<T, DefaultReselect<T>> {
+    /// Creates RetryTag, which uses `NFCTag::select()` of `tag` to select it again.
+    pub fn new(tag: T, policy: RetryPolicy) -> Self {
+        RetryTag::with_reselect(tag, policy, <T as NFCTag>::select)
+    }
+}
+
+impl<T: NFCTag, F: FnMut(&mut T) -> Option<Result<(), T::TransceiveError>>> RetryTag<T, F> {
+    /// Creates RetryTag, which calls `reselect` to select the tag again.
+    pub fn with_reselect(tag: T, policy: RetryPolicy, reselect: F) -> Self {
+        RetryTag { tag: tag, policy: policy, reselect: reselect }
+    }
+
+    /// Returns the policy.
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Returns the underlying tag.
+    pub fn into_inner(self) -> T {
+        self.tag
+    }
+
+    fn wait(&self) {
+        #[cfg(feature = "std")]
+        {
+            if let Some(backoff) = self.policy.backoff {
+                ::std::thread::sleep(backoff);
+            }
+        }
+    }
+}
+
+impl<T: NFCTag, F: FnMut(&mut T) -> Option<Result<(), T::TransceiveError>>> NFCTag for RetryTag<T, F> {
+    type TransceiveError = T::TransceiveError;
+
+    fn tag_id(&self) -> &[u8] {
+        self.tag.tag_id()
+    }
+
+    fn atqa(&self) -> Option<u16> {
+        self.tag.atqa()
+    }
+
+    fn sak(&self) -> Option<u8> {
+        self.tag.sak()
+    }
+
+    fn bcc(&self) -> Option<&[u8]> {
+        self.tag.bcc()
+    }
+
+    fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Self::TransceiveError> {
+        let is_auth = matches!(data_to_tag.first(), Some(&0x60) | Some(&0x61));
+        let retried = match data_to_tag.first() {
+            Some(&0x30) | Some(&0x60) | Some(&0x61) => true,
+            Some(&0xA0) | Some(&0xC0) | Some(&0xC1) | Some(&0xC2) | Some(&0xB0) => self.policy.write_retries,
+            _ => false,
+        };
+        let max_attempts = if retried { self.policy.max_attempts } else { 1 };
+
+        let mut attempt = 1;
+        loop {
+            match self.tag.transceive(data_to_tag, data_from_tag) {
+                Ok(len) => return Ok(len),
+                Err(error) => {
+                    if attempt >= max_attempts {
+                        return Err(error);
+                    }
+                    debug!("transceive failed (attempt {} of {}): {:?}", attempt, max_attempts, error);
+                    self.wait();
+                    if is_auth {
+                        match (self.reselect)(&mut self.tag) {
+                            Some(result) => result?,
+                            None => return Err(error),
+                        }
+                    }
+                },
+            }
+
+            attempt += 1;
+        }
+    }
+
+    fn transceive_bits(&mut self, data_to_tag: &[u8], last_byte_bits: u8, data_from_tag: &mut [u8]) -> Option<Result<usize, Self::TransceiveError>> {
+        self.tag.transceive_bits(data_to_tag, last_byte_bits, data_from_tag)
+    }
+
+    fn check_presence(&mut self) -> Option<bool> {
+        self.tag.check_presence()
+    }
+
+    fn supports_key(&self, key_option: KeyOption) -> bool {
+        self.tag.supports_key(key_option)
+    }
+
+    fn is_timeout(&self, error: &Self::TransceiveError) -> Option<bool> {
+        self.tag.is_timeout(error)
+    }
+
+    fn select(&mut self) -> Option<Result<(), Self::TransceiveError>> {
+        (self.reselect)(&mut self.tag)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{NFCTag, MifareTag, Key, KeyOption, BlockOffset, SectorNumber4K};
+    use crate::mock::{MockTag, MockError};
+    use crate::uid::Uid;
+    use super::{RetryTag, RetryPolicy};
+
+    // Tag which fails first `failures` commands without passing them to the mock
+    struct FlakyTag {
+        tag: MockTag,
+        failures: usize,
+        attempts: usize,
+        selects: usize,
+    }
+
+    impl FlakyTag {
+        fn new(failures: usize) -> Self {
+            FlakyTag { tag: MockTag::new(Uid::Single([0x01, 0x02, 0x03, 0x04])), failures: failures, attempts: 0, selects: 0 }
+        }
+    }
+
+    impl NFCTag for FlakyTag {
+        type TransceiveError = MockError;
+
+        fn tag_id(&self) -> &[u8] {
+            NFCTag::tag_id(&self.tag)
+        }
+
+        fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Self::TransceiveError> {
+            self.attempts += 1;
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(MockError::NoResponse);
+            }
+            NFCTag::transceive(&mut self.tag, data_to_tag, data_from_tag)
+        }
+
+        fn select(&mut self) -> Option<Result<(), Self::TransceiveError>> {
+            self.selects += 1;
+            self.tag.select()
+        }
+    }
+
+    #[test]
+    fn retries_until_success() {
+        let mut tag = MifareTag::new(RetryTag::new(FlakyTag::new(0), RetryPolicy::new(3))).unwrap();
+        let mut sector = tag.authenticate_sector(SectorNumber4K::new(0).unwrap(), KeyOption::KeyA, Key::DEFAULT).unwrap();
+        sector.tag.tag.tag.failures = 2;
+        let mut buf = [0; 16];
+        sector.read_block(BlockOffset::new(0).unwrap(), &mut buf).unwrap();
+        assert_eq!(buf[..4], [0x01, 0x02, 0x03, 0x04]);
+        let tag = tag.tag.into_inner();
+        assert_eq!(tag.attempts, 4);
+        // Only authentication needs selecting again
+        assert_eq!(tag.selects, 0);
+    }
+
+    #[test]
+    fn stops_at_max_attempts() {
+        let mut tag = RetryTag::new(FlakyTag::new(5), RetryPolicy::new(3));
+        assert_eq!(tag.transceive(&[0x30, 0x00], &mut [0; 16]), Err(MockError::NoResponse));
+        assert_eq!(tag.into_inner().attempts, 3);
+
+        // Zero is treated as one
+        let mut tag = RetryTag::new(FlakyTag::new(5), RetryPolicy::new(0));
+        assert_eq!(tag.transceive(&[0x30, 0x00], &mut [0; 16]), Err(MockError::NoResponse));
+        assert_eq!(tag.into_inner().attempts, 1);
+    }
+
+    #[test]
+    fn authentication_selects_before_retry() {
+        let mut tag = MifareTag::new(RetryTag::new(FlakyTag::new(2), RetryPolicy::new(3))).unwrap();
+        tag.authenticate_sector(SectorNumber4K::new(1).unwrap(), KeyOption::KeyA, Key::DEFAULT).unwrap();
+        let tag = tag.tag.into_inner();
+        assert_eq!(tag.attempts, 3);
+        assert_eq!(tag.selects, 2);
+    }
+
+    #[test]
+    fn authentication_isnt_retried_without_select() {
+        let mut tag = RetryTag::with_reselect(FlakyTag::new(1), RetryPolicy::new(3), |_: &mut FlakyTag| None);
+        let mut resp = [0; 16];
+        let auth = [0x60, 0x04, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x02, 0x03, 0x04];
+        assert_eq!(tag.transceive(&auth, &mut resp), Err(MockError::NoResponse));
+        assert_eq!(tag.into_inner().attempts, 1);
+    }
+
+    #[test]
+    fn halt_isnt_retried() {
+        let mut tag = RetryTag::new(FlakyTag::new(1), RetryPolicy::new(3));
+        assert_eq!(tag.transceive(&[0x50, 0x00], &mut [0; 16]), Err(MockError::NoResponse));
+        let tag = tag.into_inner();
+        assert_eq!(tag.attempts, 1);
+        assert_eq!(tag.selects, 0);
+    }
+
+    #[test]
+    fn writes_arent_retried_by_default() {
+        let value = [0xC0, 0x04, 0x01, 0x00, 0x00, 0x00];
+        for cmd in &[&[0xB0, 0x04][..], &value[..], &[0xA0; 18][..]] {
+            let mut tag = RetryTag::new(FlakyTag::new(1), RetryPolicy::new(3));
+            assert_eq!(tag.transceive(cmd, &mut [0; 16]), Err(MockError::NoResponse));
+            assert_eq!(tag.into_inner().attempts, 1);
+        }
+    }
+
+    #[test]
+    fn writes_are_retried_if_enabled() {
+        let policy = RetryPolicy::new(3).with_write_retries();
+        assert!(policy.write_retries());
+        let mut tag = MifareTag::new(RetryTag::new(FlakyTag::new(0), policy)).unwrap();
+        let mut sector = tag.authenticate_sector(SectorNumber4K::new(1).unwrap(), KeyOption::KeyA, Key::DEFAULT).unwrap();
+        sector.tag.tag.tag.failures = 1;
+        sector.write_block(BlockOffset::new(0).unwrap(), &[0x42; 16]).unwrap();
+        let tag = tag.tag.into_inner();
+        assert_eq!(tag.attempts, 3);
+        assert_eq!(tag.tag.image()[64..80], [0x42; 16]);
+    }
+}
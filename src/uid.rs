@@ -0,0 +1,128 @@
+/// Unique identifier of Mifare tag.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Uid {
+    /// Single-size (4 bytes) UID.
+    Single([u8; 4]),
+    /// Double-size (7 bytes) UID.
+    Double([u8; 7]),
+}
+
+impl Uid {
+    /// Creates Uid from slice, checking that it has valid length (4 or 7).
+    pub fn from_slice(uid: &[u8]) -> Option<Self> {
+        match uid.len() {
+            4 => {
+                let mut buf = [0; 4];
+                buf.copy_from_slice(uid);
+                Some(Uid::Single(buf))
+            },
+            7 => {
+                let mut buf = [0; 7];
+                buf.copy_from_slice(uid);
+                Some(Uid::Double(buf))
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns bytes of the UID.
+    pub fn as_bytes(&self) -> &[u8] {
+        match *self {
+            Uid::Single(ref uid) => uid,
+            Uid::Double(ref uid) => uid,
+        }
+    }
+
+    /// Computes BCC (XOR of UID bytes) sent by the tag in each cascade level of anticollision.
+    ///
+    /// Returns buffer and number of used bytes in it, which is 1 for single-size UID and 2
+    /// for double-size UID. BCC of cascade level 1 of double-size UID includes the cascade
+    /// tag.
+    pub fn bcc(&self) -> ([u8; 2], usize) {
+        let xor = |bytes: &[u8]| bytes.iter().fold(0, |acc, byte| acc ^ byte);
+        match *self {
+            Uid::Single(ref uid) => ([xor(uid), 0], 1),
+            Uid::Double(ref uid) => ([CASCADE_TAG ^ xor(&uid[0..3]), xor(&uid[3..7])], 2),
+        }
+    }
+
+    /// Checks BCC received from the tag during anticollision.
+    ///
+    /// Returns false if `bcc` doesn't match the one computed by `bcc()` or if single-size UID
+    /// starts with cascade tag (0x88), which means that the UID isn't complete.
+    pub fn verify_bcc(&self, bcc: &[u8]) -> bool {
+        if let Uid::Single(ref uid) = *self {
+            if uid[0] == CASCADE_TAG {
+                return false;
+            }
+        }
+        let (expected, len) = self.bcc();
+        bcc == &expected[..len]
+    }
+
+    /// Computes 4 byte non-unique ID from double-size UID.
+    ///
+    /// The derivation is described in NXP AN10927 (MIFARE and handling of UIDs). Returns
+    /// `None` for single-size UID, which is used as-is.
+    pub fn nuid(&self) -> Option<[u8; 4]> {
+        match *self {
+            Uid::Single(_) => None,
+            Uid::Double(ref uid) => {
+                let crc1 = crc_a(0x6363, &uid[0..3]);
+                let crc2 = crc_a(crc1, &uid[3..7]);
+
+                Some([((crc1 >> 8) as u8 & 0xF0) | 0x0F, crc1 as u8, (crc2 >> 8) as u8, crc2 as u8])
+            },
+        }
+    }
+}
+
+// Cascade tag, sent in place of the first byte of double-size UID in cascade level 1
+const CASCADE_TAG: u8 = 0x88;
+
+// CRC_A as defined in ISO/IEC 14443-3
+fn crc_a(init: u16, data: &[u8]) -> u16 {
+    let mut crc = init;
+    for byte in data {
+        let mut byte = *byte ^ crc as u8;
+        byte ^= byte << 4;
+        let byte = byte as u16;
+        crc = (crc >> 8) ^ (byte << 8) ^ (byte << 3) ^ (byte >> 4);
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Examples from ISO/IEC 14443-3 Annex B, CRC is transmitted least significant byte first
+    #[test]
+    fn crc_a_examples() {
+        assert_eq!(crc_a(0x6363, &[0x00, 0x00]), 0x1EA0);
+        assert_eq!(crc_a(0x6363, &[0x12, 0x34]), 0xCF26);
+    }
+
+    // Frames as seen on the air: HLTA (50 00 57 CD), READ block 0 (30 00 02 A8) and AUTH
+    // key A block 0 (60 00 F5 7B)
+    #[test]
+    fn crc_a_of_frames() {
+        assert_eq!(crc_a(0x6363, &[0x50, 0x00]), 0xCD57);
+        assert_eq!(crc_a(0x6363, &[0x30, 0x00]), 0xA802);
+        assert_eq!(crc_a(0x6363, &[0x60, 0x00]), 0x7BF5);
+    }
+
+    // Only guards against accidental changes - the value was produced by this
+    // implementation, so it doesn't verify the derivation. Replace it with the UID -> NUID
+    // example from AN10927 when it's at hand.
+    #[test]
+    fn nuid_regression() {
+        let uid = Uid::Double([0x04, 0x41, 0x82, 0xC2, 0x4A, 0x5C, 0x80]);
+        assert_eq!(uid.nuid(), Some([0x3F, 0xD1, 0xFB, 0x8A]));
+    }
+
+    #[test]
+    fn nuid_of_single_size_uid() {
+        assert_eq!(Uid::Single([0x01, 0x02, 0x03, 0x04]).nuid(), None);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{command, NFCTag, Error, DEFAULT_MAX_RESPONSE_LEN};
+
+/// Number of pages of Mifare Ultralight.
+pub const ULTRALIGHT_PAGES: u8 = 16;
+
+/// Encapsulates Mifare Ultralight (or compatible, e.g. NTAG) tag.
+///
+/// These tags have 4 byte pages and don't use authentication.
+pub struct UltralightTag<T> {
+    tag: T,
+    pages: u8,
+    max_response_len: usize,
+    dry_run: bool,
+}
+
+impl<T: NFCTag> UltralightTag<T> {
+    /// Checks whether tag_id has correct length and creates UltralightTag with 16 pages.
+    pub fn new(tag: T) -> Option<Self> {
+        UltralightTag::with_pages(tag, ULTRALIGHT_PAGES)
+    }
+
+    /// Checks whether tag_id has correct length and creates UltralightTag with given number of
+    /// pages (e.g. for NTAG tags).
+    pub fn with_pages(tag: T, pages: u8) -> Option<Self> {
+        if tag.tag_id().len() == 7 {
+            Some(UltralightTag { tag: tag, pages: pages, max_response_len: DEFAULT_MAX_RESPONSE_LEN, dry_run: false })
+        } else {
+            None
+        }
+    }
+
+    /// Returns number of pages of the tag.
+    pub fn pages(&self) -> u8 {
+        self.pages
+    }
+
+    /// Returns id of underlying tag.
+    pub fn tag_id(&self) -> &[u8] {
+        self.tag.tag_id()
+    }
+
+    /// Sets maximum length of response accepted from the reader.
+    ///
+    /// See `MifareTag::set_max_response_len()`.
+    pub fn set_max_response_len(&mut self, max_response_len: usize) {
+        self.max_response_len = max_response_len;
+    }
+
+    /// Returns maximum length of response accepted from the reader.
+    pub fn max_response_len(&self) -> usize {
+        self.max_response_len
+    }
+
+    /// Enables or disables dry run mode.
+    ///
+    /// In dry run mode, `write_page()` only logs (at info level) what it would write and
+    /// returns success without sending anything. See `MifareTag::set_dry_run()`.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// Returns true if dry run mode is enabled.
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Sends arbitrary data to the tag and receives response.
+    ///
+    /// See `MifareTag::transceive_raw()`.
+    pub fn transceive_raw(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Error<T::TransceiveError>> {
+        let max = ::core::cmp::min(data_from_tag.len(), self.max_response_len);
+        let len = self.tag.transceive(data_to_tag, &mut data_from_tag[..max]).map_err(Error::Transceive)?;
+        command::check_response_len(len, max)
+    }
+
+    /// Reads pages starting at `start` until `buf` is filled
+    ///
+    /// Fails with `PageOutOfRange` without reading anything if the pages don't fit into the
+    /// tag.
+    pub fn read_pages(&mut self, start: u8, buf: &mut [u8]) -> Result<(), Error<T::TransceiveError>> {
+        let page_count = buf.len().div_ceil(4);
+        if start as usize + page_count > self.pages as usize {
+            return Err(Error::PageOutOfRange(start));
+        }
+
+        // Each read returns four pages
+        for (i, chunk) in buf.chunks_mut(16).enumerate() {
+            let read_cmd = [0x30, start + i as u8 * 4];
+            let mut resp = [0; 16];
+            let len = self.transceive_raw(&read_cmd, &mut resp)?;
+            if len != resp.len() {
+                return Err(Error::UnexpectedLength { len: len, expected: resp.len() });
+            }
+            chunk.copy_from_slice(&resp[..chunk.len()]);
+        }
+        Ok(())
+    }
+
+    /// Writes 4 bytes of data to given page
+    ///
+    /// WARNING: Writing pages 2 and 3 (lock bytes and OTP) is irreversible!
+    pub fn write_page(&mut self, page: u8, data: &[u8; 4]) -> Result<(), Error<T::TransceiveError>> {
+        if page >= self.pages {
+            return Err(Error::PageOutOfRange(page));
+        }
+
+        if self.dry_run {
+            info!("dry run: would write page {}: {:02x?}", page, data);
+            return Ok(());
+        }
+
+        let write_cmd = [0xA2, page, data[0], data[1], data[2], data[3]];
+        let mut resp = [0; 16];
+        let len = self.transceive_raw(&write_cmd, &mut resp)?;
+        command::check_ack(&resp[..len])
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::{NFCTag, Error};
+    use crate::mock::MockError;
+    use super::UltralightTag;
+
+    // Tag answering READ with page numbers and WRITE with ACK, counting writes. It reports
+    // whole response even if it doesn't fit into the buffer.
+    struct PageTag {
+        writes: usize,
+    }
+
+    impl NFCTag for PageTag {
+        type TransceiveError = MockError;
+
+        fn tag_id(&self) -> &[u8] {
+            &[0x04, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66]
+        }
+
+        fn transceive(&mut self, data_to_tag: &[u8], data_from_tag: &mut [u8]) -> Result<usize, Self::TransceiveError> {
+            match data_to_tag[0] {
+                0x30 => {
+                    for (i, byte) in data_from_tag.iter_mut().take(16).enumerate() {
+                        *byte = data_to_tag[1] + i as u8 / 4;
+                    }
+                    Ok(16)
+                },
+                0xA2 => {
+                    self.writes += 1;
+                    data_from_tag[0] = 0x0A;
+                    Ok(1)
+                },
+                cmd => Err(MockError::UnsupportedCommand(cmd)),
+            }
+        }
+    }
+
+    #[test]
+    fn dry_run_doesnt_write() {
+        let mut tag = UltralightTag::new(PageTag { writes: 0 }).unwrap();
+        tag.write_page(4, &[0x11; 4]).unwrap();
+        assert_eq!(tag.tag.writes, 1);
+
+        tag.set_dry_run(true);
+        assert!(tag.is_dry_run());
+        tag.write_page(4, &[0x11; 4]).unwrap();
+        assert_eq!(tag.tag.writes, 1);
+    }
+
+    #[test]
+    fn read_pages_respects_max_response_len() {
+        let mut tag = UltralightTag::new(PageTag { writes: 0 }).unwrap();
+        let mut buf = [0; 20];
+        tag.read_pages(4, &mut buf).unwrap();
+        assert_eq!(buf[..16], [4, 4, 4, 4, 5, 5, 5, 5, 6, 6, 6, 6, 7, 7, 7, 7]);
+        assert_eq!(buf[16..], [8; 4]);
+
+        tag.set_max_response_len(8);
+        match tag.read_pages(4, &mut buf) {
+            Err(Error::ResponseTooLong { len: 16, max: 8 }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
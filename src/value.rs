@@ -0,0 +1,133 @@
+use core::fmt;
+
+/// Error returned when decoding invalid value block.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ValueBlockError {
+    /// Copies of the value don't match.
+    ValueMismatch,
+    /// Copies of the address don't match.
+    AddressMismatch,
+}
+
+impl fmt::Display for ValueBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValueBlockError::ValueMismatch => write!(f, "copies of value in value block don't match"),
+            ValueBlockError::AddressMismatch => write!(f, "copies of address in value block don't match"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for ValueBlockError {}
+
+/// Value block - signed 32 bit value with redundant copies.
+///
+/// Layout of the block is: value, inverted value, value (4 bytes each, little endian) followed
+/// by address, inverted address, address, inverted address (1 byte each).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ValueBlock {
+    value: i32,
+    address: u8,
+}
+
+impl ValueBlock {
+    /// Creates value block.
+    ///
+    /// Address is not interpreted by the tag, it's usually used to store address of backup
+    /// block.
+    pub fn new(value: i32, address: u8) -> Self {
+        ValueBlock { value: value, address: address }
+    }
+
+    /// Returns the value.
+    pub fn value(&self) -> i32 {
+        self.value
+    }
+
+    /// Returns the address.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Encodes value block into its 16 byte representation.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let value = self.value as u32;
+        let mut buf = [0; 16];
+        for i in 0..4 {
+            let byte = (value >> (i * 8)) as u8;
+            buf[i] = byte;
+            buf[i + 4] = !byte;
+            buf[i + 8] = byte;
+        }
+        buf[12] = self.address;
+        buf[13] = !self.address;
+        buf[14] = self.address;
+        buf[15] = !self.address;
+        buf
+    }
+
+    /// Decodes value block while checking all redundant copies.
+    pub fn from_bytes(buf: &[u8; 16]) -> Result<Self, ValueBlockError> {
+        for i in 0..4 {
+            if buf[i] != !buf[i + 4] || buf[i] != buf[i + 8] {
+                return Err(ValueBlockError::ValueMismatch);
+            }
+        }
+        if buf[12] != !buf[13] || buf[12] != buf[14] || buf[12] != !buf[15] {
+            return Err(ValueBlockError::AddressMismatch);
+        }
+
+        let value = (buf[0] as u32) | (buf[1] as u32) << 8 | (buf[2] as u32) << 16 | (buf[3] as u32) << 24;
+        Ok(ValueBlock::new(value as i32, buf[12]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: [u8; 16] = [0x78, 0x56, 0x34, 0x12, 0x87, 0xA9, 0xCB, 0xED, 0x78, 0x56, 0x34, 0x12, 0x05, 0xFA, 0x05, 0xFA];
+
+    #[test]
+    fn layout() {
+        assert_eq!(ValueBlock::new(0x12345678, 0x05).to_bytes(), BLOCK);
+        assert_eq!(ValueBlock::from_bytes(&BLOCK), Ok(ValueBlock::new(0x12345678, 0x05)));
+    }
+
+    #[test]
+    fn negative_values() {
+        let minus_one = [0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0xFF, 0x00, 0xFF];
+        assert_eq!(ValueBlock::new(-1, 0).to_bytes(), minus_one);
+        assert_eq!(ValueBlock::from_bytes(&minus_one).unwrap().value(), -1);
+
+        let minus_hundred = [0x9C, 0xFF, 0xFF, 0xFF, 0x63, 0x00, 0x00, 0x00, 0x9C, 0xFF, 0xFF, 0xFF, 0x04, 0xFB, 0x04, 0xFB];
+        assert_eq!(ValueBlock::new(-100, 4).to_bytes(), minus_hundred);
+        assert_eq!(ValueBlock::from_bytes(&minus_hundred), Ok(ValueBlock::new(-100, 4)));
+
+        for &value in &[i32::MIN, i32::MAX, 0] {
+            let block = ValueBlock::new(value, 0x80);
+            assert_eq!(ValueBlock::from_bytes(&block.to_bytes()), Ok(block));
+        }
+    }
+
+    #[test]
+    fn value_mismatch() {
+        // Each copy of the value is checked
+        for &i in &[0, 3, 4, 7, 8, 11] {
+            let mut buf = BLOCK;
+            buf[i] ^= 0x01;
+            assert_eq!(ValueBlock::from_bytes(&buf), Err(ValueBlockError::ValueMismatch));
+        }
+    }
+
+    #[test]
+    fn address_mismatch() {
+        // Each copy of the address is checked
+        for i in 12..16 {
+            let mut buf = BLOCK;
+            buf[i] ^= 0x01;
+            assert_eq!(ValueBlock::from_bytes(&buf), Err(ValueBlockError::AddressMismatch));
+        }
+    }
+}